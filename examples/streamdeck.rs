@@ -21,6 +21,10 @@ struct Options {
     #[structopt(flatten)]
     filter: Filter,
 
+    #[structopt(long)]
+    /// Save a PNG snapshot of the deck after running the command
+    snapshot: Option<String>,
+
     #[structopt(long = "log-level", default_value = "info")]
     /// Enable verbose logging
     level: LevelFilter,
@@ -76,11 +80,47 @@ pub enum Commands {
         #[structopt(flatten)]
         opts: ImageOptions,
     },
+    /// Set button text
+    #[cfg(feature = "default-font")]
+    SetText {
+        /// Index of button to be set
+        key: u8,
+
+        /// Text to be displayed, `\n` starts a new line
+        text: String,
+
+        #[cfg(feature = "fonts")]
+        #[structopt(long)]
+        /// Installed font family to use in place of the default font
+        font: Option<String>,
+
+        #[structopt(long, default_value = "16")]
+        /// Text height in pixels
+        size: f32,
+
+        #[structopt(long)]
+        /// Wrap text to fit the key
+        wrap: bool,
+    },
     SetLcdImage {
         x: u16,
         y: u16,
         file: String,
     },
+    /// Clear a button to black
+    ClearKey {
+        /// Index of button to be cleared
+        key: u8,
+    },
+    /// Clear all buttons to black
+    ClearAll,
+    /// Fill all buttons with a colour
+    FillAll {
+        /// Colour (hex, CSS name or hsl)
+        colour: Colour,
+    },
+    /// Clear the LCD to black
+    ClearLcd,
 }
 
 fn main() {
@@ -110,6 +150,14 @@ fn main() {
     if let Err(e) = do_command(&mut deck, opts.cmd) {
         error!("Command error: {:?}", e);
     }
+
+    // Save a snapshot of the images written by the command
+    if let Some(path) = opts.snapshot {
+        info!("Saving snapshot to: {}", path);
+        if let Err(e) = deck.snapshot().save(&path) {
+            error!("Error saving snapshot: {:?}", e);
+        }
+    }
 }
 
 fn do_command(deck: &mut StreamDeck, cmd: Commands) -> Result<(), Error> {
@@ -152,6 +200,45 @@ fn do_command(deck: &mut StreamDeck, cmd: Commands) -> Result<(), Error> {
             info!("Setting key {} to image: {}", key, file);
             deck.set_button_file(key, &file, &opts)?;
         }
+        #[cfg(feature = "default-font")]
+        Commands::SetText{key, text, #[cfg(feature = "fonts")] font, size, wrap} => {
+            use streamdeck::{fonts, text::Scale, TextOptions, TextPosition};
+
+            #[allow(unused_mut)]
+            let mut f = fonts::default_font();
+            #[cfg(feature = "fonts")]
+            if let Some(family) = font {
+                let mut library = fonts::FontLibrary::system();
+                match library.find(&family, fonts::WEIGHT_NORMAL, fonts::FontStyle::Normal) {
+                    Some(v) => f = v,
+                    None => warn!("Font family '{}' not found, using default font", family),
+                }
+            }
+
+            let text = text.replace("\\n", "\n");
+            let opts = TextOptions::default()
+                .with_scale(Scale::uniform(size))
+                .with_wrap(wrap);
+
+            info!("Setting key {} text to: {:?}", key, text);
+            deck.set_button_text(key, &f, &TextPosition::centred(), &text, &opts)?;
+        },
+        Commands::ClearKey{key} => {
+            info!("Clearing key {}", key);
+            deck.clear_key(key)?;
+        },
+        Commands::ClearAll => {
+            info!("Clearing all keys");
+            deck.clear_all()?;
+        },
+        Commands::FillAll{colour} => {
+            info!("Filling all keys with: {:?}", colour);
+            deck.fill_all(&colour)?;
+        },
+        Commands::ClearLcd => {
+            info!("Clearing lcd");
+            deck.clear_lcd()?;
+        },
         Commands::SetLcdImage{x, y, file} => {
             info!("writing {} to {},{}", file, x, y);
 
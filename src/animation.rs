@@ -0,0 +1,389 @@
+//! Property animations
+//!
+//! A [Tween] interpolates a value (a colour, a gauge value, a text position)
+//! over time with an [Easing] curve. Animations render a key image from the
+//! current value on each [Animator::tick], and only keys whose image actually
+//! changed are written. Time is read from an injectable [Clock] so animations
+//! can be driven deterministically (see [ManualClock]).
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbImage};
+
+use crate::framebuffer::Framebuffer;
+use crate::images::Colour;
+use crate::transitions::FRAME_INTERVAL;
+use crate::{Error, StreamDeck};
+
+/// Source of time for animations
+pub trait Clock {
+    /// Time elapsed since an arbitrary fixed point
+    fn now(&self) -> Duration;
+}
+
+/// Clock using the system monotonic time
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Manually advanced clock for deterministic animations
+///
+/// Clones share the same time, so a clone can be kept to advance an
+/// [Animator] that owns the clock.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forwards
+    pub fn advance(&self, d: Duration) {
+        self.nanos.fetch_add(d.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Set the current time
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+}
+
+/// Easing curve mapping linear progress to eased progress
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Easing {
+    Linear,
+    EaseInQuad,
+    EaseOutQuad,
+    EaseInOutQuad,
+    EaseInCubic,
+    EaseOutCubic,
+    EaseInOutCubic,
+    /// Jump to the end value at the end of the animation
+    Step,
+}
+
+impl Easing {
+    /// Apply the curve to progress `t` (0.0 to 1.0)
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInQuad => t * t,
+            Easing::EaseOutQuad => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOutQuad if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOutQuad => 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0,
+            Easing::EaseInCubic => t * t * t,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOutCubic => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+            Easing::Step if t < 1.0 => 0.0,
+            Easing::Step => 1.0,
+        }
+    }
+}
+
+/// Values that can be interpolated
+pub trait Lerp {
+    /// Interpolate towards `other` by `t` (0.0 to 1.0)
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for i32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        (*self as f32).lerp(&(*other as f32), t).round() as i32
+    }
+}
+
+impl<A: Lerp, B: Lerp> Lerp for (A, B) {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        (self.0.lerp(&other.0, t), self.1.lerp(&other.1, t))
+    }
+}
+
+impl Lerp for Colour {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let l = |a: u8, b: u8| (a as f32).lerp(&(b as f32), t).round() as u8;
+        Colour::rgba(l(self.r, other.r), l(self.g, other.g), l(self.b, other.b), l(self.a, other.a))
+    }
+}
+
+/// Interpolation between two values over time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tween<T> {
+    from: T,
+    to: T,
+    start: Duration,
+    delay: Duration,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl<T: Lerp + Clone> Tween<T> {
+    /// Create a linear tween, starting when added to an [Animator]
+    pub fn new(from: T, to: T, duration: Duration) -> Self {
+        Self {
+            from,
+            to,
+            start: Duration::ZERO,
+            delay: Duration::ZERO,
+            duration,
+            easing: Easing::Linear,
+        }
+    }
+
+    /// Set the easing curve
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Delay the start of the tween
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set the clock time the tween starts from (before any delay)
+    pub fn start(&mut self, now: Duration) {
+        self.start = now;
+    }
+
+    /// Linear progress (0.0 to 1.0) at the provided time
+    pub fn progress(&self, now: Duration) -> f32 {
+        let elapsed = now.saturating_sub(self.start + self.delay);
+        if self.duration.is_zero() {
+            return if now >= self.start + self.delay { 1.0 } else { 0.0 };
+        }
+        (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+
+    /// Value at the provided time
+    pub fn value(&self, now: Duration) -> T {
+        self.from.lerp(&self.to, self.easing.apply(self.progress(now)))
+    }
+
+    pub fn is_finished(&self, now: Duration) -> bool {
+        now >= self.start + self.delay + self.duration
+    }
+}
+
+/// Animated key content
+pub trait Animation {
+    /// Called with the clock time when the animation is added to an [Animator]
+    fn start(&mut self, _now: Duration) {}
+
+    /// Render the key image at the provided time
+    fn render(&mut self, now: Duration, width: u32, height: u32) -> RgbImage;
+
+    /// Check whether the animation has completed
+    fn is_finished(&self, now: Duration) -> bool;
+}
+
+/// Animation rendering a key from a single tweened value
+pub struct TweenAnimation<T, F> {
+    tween: Tween<T>,
+    render: F,
+}
+
+impl<T, F> TweenAnimation<T, F>
+where
+    T: Lerp + Clone,
+    F: FnMut(&T, u32, u32) -> RgbImage,
+{
+    /// Create an animation calling `render` with the current tween value
+    pub fn new(tween: Tween<T>, render: F) -> Self {
+        Self { tween, render }
+    }
+}
+
+impl<T, F> Animation for TweenAnimation<T, F>
+where
+    T: Lerp + Clone,
+    F: FnMut(&T, u32, u32) -> RgbImage,
+{
+    fn start(&mut self, now: Duration) {
+        self.tween.start(now);
+    }
+
+    fn render(&mut self, now: Duration, width: u32, height: u32) -> RgbImage {
+        let value = self.tween.value(now);
+        (self.render)(&value, width, height)
+    }
+
+    fn is_finished(&self, now: Duration) -> bool {
+        self.tween.is_finished(now)
+    }
+}
+
+/// Runs animations on keys
+pub struct Animator<C: Clock> {
+    clock: C,
+    keys: BTreeMap<u8, Box<dyn Animation>>,
+}
+
+impl<C: Clock> Animator<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Animate a key, replacing any running animation on that key
+    pub fn add(&mut self, key: u8, mut animation: impl Animation + 'static) {
+        animation.start(self.clock.now());
+        self.keys.insert(key, Box::new(animation));
+    }
+
+    /// Stop animating a key, leaving the current frame displayed
+    pub fn remove(&mut self, key: u8) {
+        self.keys.remove(&key);
+    }
+
+    /// Check whether any animations are running
+    pub fn is_active(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Render the current frame of each animation, returning only those that differ
+    /// from the displayed image. Finished animations are removed after their final frame.
+    pub fn changed_frames(&mut self, framebuffer: &Framebuffer, width: u32, height: u32) -> Vec<(u8, RgbImage)> {
+        let now = self.clock.now();
+        let mut frames = vec![];
+
+        for (key, animation) in self.keys.iter_mut() {
+            let image = animation.render(now, width, height);
+            if framebuffer.key(*key) != Some(&image) {
+                frames.push((*key, image));
+            }
+        }
+
+        self.keys.retain(|_, a| !a.is_finished(now));
+        frames
+    }
+
+    /// Render and write changed frames, returning the number of keys written
+    pub fn tick(&mut self, deck: &mut StreamDeck) -> Result<usize, Error> {
+        let (w, h) = deck.image_size();
+        let frames = self.changed_frames(deck.framebuffer(), w as u32, h as u32);
+        let n = frames.len();
+
+        deck.set_buttons(frames.into_iter().map(|(k, i)| (k, DynamicImage::ImageRgb8(i))))?;
+        Ok(n)
+    }
+
+    /// Tick until all animations have finished
+    ///
+    /// This sleeps between frames so is only useful with a real time clock.
+    pub fn run(&mut self, deck: &mut StreamDeck) -> Result<(), Error> {
+        while self.is_active() {
+            let start = Instant::now();
+            self.tick(deck)?;
+
+            if let Some(wait) = FRAME_INTERVAL.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::info::Kind;
+    use image::Rgb;
+
+    #[test]
+    fn easing_curves() {
+        for e in [Easing::Linear, Easing::EaseInQuad, Easing::EaseOutQuad, Easing::EaseInOutQuad, Easing::EaseInOutCubic] {
+            assert_eq!(e.apply(0.0), 0.0, "{:?}", e);
+            assert_eq!(e.apply(1.0), 1.0, "{:?}", e);
+        }
+        assert_eq!(Easing::EaseInOutCubic.apply(0.5), 0.5);
+        assert!(Easing::EaseInQuad.apply(0.5) < 0.5);
+        assert_eq!(Easing::Step.apply(0.99), 0.0);
+    }
+
+    #[test]
+    fn tween_with_manual_clock() {
+        let clock = ManualClock::new();
+        clock.set(Duration::from_secs(10));
+
+        let mut tween = Tween::new(Colour::rgb(0, 0, 0), Colour::rgb(200, 100, 0), Duration::from_millis(100))
+            .delay(Duration::from_millis(50));
+        tween.start(clock.now());
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(tween.value(clock.now()), Colour::rgb(0, 0, 0));
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(tween.value(clock.now()), Colour::rgb(100, 50, 0));
+        assert!(!tween.is_finished(clock.now()));
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(tween.value(clock.now()), Colour::rgb(200, 100, 0));
+        assert!(tween.is_finished(clock.now()));
+    }
+
+    #[test]
+    fn only_changed_frames() {
+        let clock = ManualClock::new();
+        let mut animator = Animator::new(clock.clone());
+        let mut fb = Framebuffer::new(Kind::Mini);
+
+        // Step easing holds the first value until the end
+        let tween = Tween::new(0, 255, Duration::from_millis(100)).easing(Easing::Step);
+        animator.add(2, TweenAnimation::new(tween, |v: &i32, w, h| {
+            RgbImage::from_pixel(w, h, Rgb([*v as u8, 0, 0]))
+        }));
+
+        let frames = animator.changed_frames(&fb, 8, 8);
+        assert_eq!(frames.len(), 1);
+        fb.set_key(2, frames[0].1.clone());
+
+        clock.advance(Duration::from_millis(50));
+        assert!(animator.changed_frames(&fb, 8, 8).is_empty());
+
+        clock.advance(Duration::from_millis(50));
+        let frames = animator.changed_frames(&fb, 8, 8);
+        assert_eq!(frames[0].1.get_pixel(0, 0).0, [255, 0, 0]);
+        assert!(!animator.is_active());
+    }
+}
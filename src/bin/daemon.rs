@@ -0,0 +1,127 @@
+
+#[macro_use] extern crate log;
+extern crate simplelog;
+use simplelog::{TermLogger, LevelFilter, TerminalMode, ColorChoice};
+
+extern crate structopt;
+use structopt::StructOpt;
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use streamdeck::{StreamDeck, Filter, Config, Error};
+
+#[derive(StructOpt)]
+#[structopt(name = "streamdeck-daemon", about = "Run an Elgato StreamDeck from a TOML or YAML config")]
+struct Options {
+    /// Config file (`.toml`, `.yaml` or `.yml`)
+    config: PathBuf,
+
+    #[structopt(flatten)]
+    filter: Filter,
+
+    #[structopt(long, default_value = "2s")]
+    /// Delay between connection attempts
+    retry: humantime::Duration,
+
+    #[structopt(long = "log-level", default_value = "info")]
+    /// Enable verbose logging
+    level: LevelFilter,
+}
+
+fn main() {
+    // Parse options
+    let opts = Options::from_args();
+
+    // Setup logging
+    let mut config = simplelog::ConfigBuilder::new();
+    config.set_time_level(LevelFilter::Off);
+
+    TermLogger::init(opts.level, config.build(), TerminalMode::Mixed, ColorChoice::Auto).unwrap();
+
+    // Load config, relative icon paths are resolved against the config directory
+    let config = match Config::load(&opts.config) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Error loading config '{}': {}", opts.config.display(), e);
+            return
+        }
+    };
+    let base = opts.config.parent().unwrap_or_else(|| Path::new(".")).to_path_buf();
+
+    // Run the device, reconnecting on device errors
+    loop {
+        let mut deck = match StreamDeck::connect(opts.filter.vid, opts.filter.pid, opts.filter.serial.clone()) {
+            Ok(d) => d,
+            Err(e) => {
+                debug!("Error connecting to streamdeck: {:?}", e);
+                std::thread::sleep(*opts.retry);
+                continue
+            }
+        };
+
+        match run(&mut deck, &config, &base) {
+            Err(Error::Config(e)) => {
+                error!("Error in config '{}': {}", opts.config.display(), e);
+                return
+            }
+            Err(Error::Hid(e)) => warn!("Device error: {:?}, reconnecting", e),
+            Err(e) => {
+                error!("Error running device: {}", e);
+                return
+            }
+            Ok(()) => return,
+        }
+
+        std::thread::sleep(*opts.retry);
+    }
+}
+
+fn run(deck: &mut StreamDeck, config: &Config, base: &Path) -> Result<(), Error> {
+    let serial = deck.serial()?;
+    info!("Connected to device (kind: {:?} serial: {})", deck.kind(), serial);
+
+    let device = config.device(&serial)
+        .ok_or_else(|| Error::Config(format!("no config for device serial {}", serial)))?;
+
+    // Build the pages first so config errors are reported before touching the device
+    let mut pages = device.page_stack(deck.kind(), base)?;
+
+    if let Some(brightness) = device.brightness {
+        deck.set_brightness(brightness)?;
+    }
+    pages.draw(deck)?;
+
+    // Running commands, reaped as they exit
+    let mut children: Vec<Child> = vec![];
+
+    loop {
+        children.retain_mut(|c| !matches!(c.try_wait(), Ok(Some(_)) | Err(_)));
+
+        let states = match deck.read_buttons(Some(Duration::from_secs(1))) {
+            Ok(s) => s,
+            Err(Error::NoData) => continue,
+            Err(e) => return Err(e),
+        };
+
+        // Routing errors are not device failures, so report them and redraw the current page
+        let commands = match pages.handle_buttons(deck, &states) {
+            Ok(c) => c,
+            Err(e @ Error::Hid(_)) => return Err(e),
+            Err(e) => {
+                error!("Error handling buttons: {}", e);
+                pages.draw(deck)?;
+                continue
+            }
+        };
+
+        for command in commands {
+            info!("Running: {}", command);
+            match Command::new("sh").arg("-c").arg(&command).spawn() {
+                Ok(c) => children.push(c),
+                Err(e) => error!("Error running '{}': {:?}", command, e),
+            }
+        }
+    }
+}
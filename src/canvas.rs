@@ -0,0 +1,291 @@
+//! Antialiased vector drawing
+//!
+//! A [Canvas] is a CPU rendered [tiny_skia] pixmap sized for a single key, a
+//! horizontal span of keys (including the physical gaps between them), or the
+//! LCD strip. Shapes, paths and text are drawn on the host and then written to
+//! the device with [Canvas::commit] using the normal image pipeline.
+
+use image::{Rgb, RgbImage};
+pub use tiny_skia::{Path, PathBuilder, Rect};
+use tiny_skia::{
+    Color, FillRule, GradientStop, LineCap, LinearGradient, Paint, Pixmap, Point, Shader, SpreadMode,
+    Stroke, Transform,
+};
+
+use crate::images::Colour;
+use crate::info::Kind;
+pub use crate::surface::Target;
+#[cfg(feature = "text")]
+use crate::text::{self, TextOptions, TextPosition, Typeface};
+use crate::{Error, StreamDeck};
+
+/// Paint used to fill or stroke shapes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Brush {
+    /// Solid colour
+    Solid(Colour),
+    /// Linear gradient between two points, with stops at offsets from 0.0 to 1.0
+    Linear {
+        from: (f32, f32),
+        to: (f32, f32),
+        stops: Vec<(f32, Colour)>,
+    },
+}
+
+impl From<Colour> for Brush {
+    fn from(c: Colour) -> Self {
+        Brush::Solid(c)
+    }
+}
+
+/// Vector drawing canvas for keys or the LCD
+pub struct Canvas {
+    kind: Kind,
+    target: Target,
+    pixmap: Pixmap,
+}
+
+impl Canvas {
+    /// Create a canvas for a single key
+    pub fn for_key(kind: Kind, key: u8) -> Result<Self, Error> {
+        Self::new(kind, Target::Key(key))
+    }
+
+    /// Create a canvas spanning `count` adjacent keys in a row, starting at `first`
+    pub fn for_keys(kind: Kind, first: u8, count: u8) -> Result<Self, Error> {
+        Self::new(kind, Target::Keys { first, count })
+    }
+
+    /// Create a canvas for the LCD strip
+    pub fn for_lcd(kind: Kind) -> Result<Self, Error> {
+        Self::new(kind, Target::Lcd)
+    }
+
+    /// Create a canvas for the provided target
+    pub fn new(kind: Kind, target: Target) -> Result<Self, Error> {
+        let (w, h) = target.size(kind)?;
+        let pixmap = Pixmap::new(w, h).ok_or(Error::InvalidImageSize)?;
+        Ok(Self { kind, target, pixmap })
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    pub fn width(&self) -> u32 {
+        self.pixmap.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.pixmap.height()
+    }
+
+    /// Access the underlying pixmap for drawing not covered by the helpers
+    pub fn pixmap_mut(&mut self) -> &mut Pixmap {
+        &mut self.pixmap
+    }
+
+    /// Fill the whole canvas with a colour
+    pub fn clear(&mut self, colour: &Colour) {
+        self.pixmap.fill(color(colour));
+    }
+
+    /// Fill a rectangle with rounded corners of `radius` pixels
+    pub fn fill_rounded_rect(&mut self, rect: Rect, radius: f32, brush: &Brush) {
+        if let Some(path) = rounded_rect(rect, radius) {
+            self.fill_path(&path, brush);
+        }
+    }
+
+    /// Stroke the outline of a rectangle with rounded corners
+    pub fn stroke_rounded_rect(&mut self, rect: Rect, radius: f32, width: f32, brush: &Brush) {
+        if let Some(path) = rounded_rect(rect, radius) {
+            self.stroke_path(&path, width, brush);
+        }
+    }
+
+    /// Fill a circle
+    pub fn fill_circle(&mut self, cx: f32, cy: f32, radius: f32, brush: &Brush) {
+        if let Some(path) = PathBuilder::from_circle(cx, cy, radius) {
+            self.fill_path(&path, brush);
+        }
+    }
+
+    /// Stroke an arc with round caps
+    ///
+    /// Angles are in degrees, clockwise from the positive x axis (3 o'clock).
+    pub fn stroke_arc(&mut self, centre: (f32, f32), radius: f32, start: f32, sweep: f32, width: f32, brush: &Brush) {
+        if let Some(path) = arc(centre, radius, start, sweep) {
+            let paint = paint(brush);
+            let stroke = Stroke {
+                width,
+                line_cap: LineCap::Round,
+                ..Default::default()
+            };
+            self.pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+
+    /// Fill a path (non-zero winding)
+    pub fn fill_path(&mut self, path: &Path, brush: &Brush) {
+        self.pixmap
+            .fill_path(path, &paint(brush), FillRule::Winding, Transform::identity(), None);
+    }
+
+    /// Stroke a path with a line of `width` pixels
+    pub fn stroke_path(&mut self, path: &Path, width: f32, brush: &Brush) {
+        let stroke = Stroke {
+            width,
+            ..Default::default()
+        };
+        self.pixmap
+            .stroke_path(path, &paint(brush), &stroke, Transform::identity(), None);
+    }
+
+    /// Draw text using the layout and styling of [TextOptions]
+    ///
+    /// The text background colour is not drawn.
+    #[cfg(feature = "text")]
+    pub fn draw_text<F: Typeface + ?Sized>(&mut self, font: &F, pos: &TextPosition, text: &str, opts: &TextOptions) {
+        let mut image = self.to_image();
+        text::draw_text(&mut image, font, pos, text, opts);
+
+        for (p, c) in self.pixmap.pixels_mut().iter_mut().zip(image.pixels()) {
+            *p = tiny_skia::ColorU8::from_rgba(c.0[0], c.0[1], c.0[2], 255).premultiply();
+        }
+    }
+
+    /// Flatten the canvas to an RGB image, with transparent areas drawn black
+    pub fn to_image(&self) -> RgbImage {
+        let mut image = RgbImage::new(self.width(), self.height());
+        for (p, c) in image.pixels_mut().zip(self.pixmap.pixels()) {
+            // Premultiplied colours are already composited over black
+            *p = Rgb([c.red(), c.green(), c.blue()]);
+        }
+        image
+    }
+
+    /// Write the canvas to the device
+    ///
+    /// Key spans are split into per-key images, discarding the gaps between keys.
+    pub fn commit(&self, deck: &mut StreamDeck) -> Result<(), Error> {
+        if deck.kind() != self.kind {
+            return Err(Error::InvalidImageSize);
+        }
+        self.target.write(deck, &self.to_image())
+    }
+}
+
+fn color(c: &Colour) -> Color {
+    Color::from_rgba8(c.r, c.g, c.b, c.a)
+}
+
+fn paint(brush: &Brush) -> Paint<'static> {
+    let shader = match brush {
+        Brush::Solid(c) => Shader::SolidColor(color(c)),
+        Brush::Linear { from, to, stops } => {
+            let stops = stops.iter().map(|(o, c)| GradientStop::new(*o, color(c))).collect();
+            LinearGradient::new(
+                Point::from_xy(from.0, from.1),
+                Point::from_xy(to.0, to.1),
+                stops,
+                SpreadMode::Pad,
+                Transform::identity(),
+            )
+            .unwrap_or(Shader::SolidColor(Color::TRANSPARENT))
+        }
+    };
+
+    Paint {
+        shader,
+        anti_alias: true,
+        ..Default::default()
+    }
+}
+
+/// Control point distance for approximating a quarter circle with a cubic
+const KAPPA: f32 = 0.552_284_8;
+
+/// Build a rounded rectangle path
+pub fn rounded_rect(rect: Rect, radius: f32) -> Option<Path> {
+    let r = radius.clamp(0.0, rect.width().min(rect.height()) / 2.0);
+    if r == 0.0 {
+        return Some(PathBuilder::from_rect(rect));
+    }
+
+    let k = r * (1.0 - KAPPA);
+    let (x, y, x1, y1) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+
+    let mut pb = PathBuilder::new();
+    pb.move_to(x + r, y);
+    pb.line_to(x1 - r, y);
+    pb.cubic_to(x1 - k, y, x1, y + k, x1, y + r);
+    pb.line_to(x1, y1 - r);
+    pb.cubic_to(x1, y1 - k, x1 - k, y1, x1 - r, y1);
+    pb.line_to(x + r, y1);
+    pb.cubic_to(x + k, y1, x, y1 - k, x, y1 - r);
+    pb.line_to(x, y + r);
+    pb.cubic_to(x, y + k, x + k, y, x + r, y);
+    pb.close();
+    pb.finish()
+}
+
+/// Build a circular arc path, see [Canvas::stroke_arc] for angles
+pub fn arc(centre: (f32, f32), radius: f32, start: f32, sweep: f32) -> Option<Path> {
+    let (cx, cy) = centre;
+    let sweep = sweep.clamp(-360.0, 360.0);
+    let segments = (sweep.abs() / 90.0).ceil().max(1.0) as usize;
+    let step = sweep.to_radians() / segments as f32;
+
+    // Control point distance for a segment of `step` radians
+    let k = 4.0 / 3.0 * (step / 4.0).tan() * radius;
+    let point = |a: f32| (cx + radius * a.cos(), cy + radius * a.sin());
+
+    let mut a = start.to_radians();
+    let mut pb = PathBuilder::new();
+    let (x, y) = point(a);
+    pb.move_to(x, y);
+
+    for _ in 0..segments {
+        let b = a + step;
+        let (x0, y0) = point(a);
+        let (x1, y1) = point(b);
+        pb.cubic_to(
+            x0 - k * a.sin(),
+            y0 + k * a.cos(),
+            x1 + k * b.sin(),
+            y1 - k * b.cos(),
+            x1,
+            y1,
+        );
+        a = b;
+    }
+
+    pb.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn draw_shapes() {
+        let mut canvas = Canvas::for_key(Kind::Mk2, 0).unwrap();
+        canvas.clear(&Colour::rgb(0, 0, 255));
+        let rect = Rect::from_xywh(8.0, 8.0, 56.0, 56.0).unwrap();
+        canvas.fill_rounded_rect(rect, 12.0, &Colour::rgb(255, 0, 0).into());
+
+        let image = canvas.to_image();
+        assert_eq!(image.get_pixel(36, 36).0, [255, 0, 0]);
+        // Rounded corners leave the background showing
+        assert_eq!(image.get_pixel(9, 9).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(2, 2).0, [0, 0, 255]);
+
+        // Quarter arc clockwise from 3 o'clock ends at the bottom
+        let mut canvas = Canvas::for_key(Kind::Mk2, 0).unwrap();
+        canvas.stroke_arc((36.0, 36.0), 30.0, 0.0, 90.0, 4.0, &Colour::rgb(255, 255, 255).into());
+        let image = canvas.to_image();
+        assert_eq!(image.get_pixel(57, 57).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(6, 36).0, [0, 0, 0]);
+    }
+}
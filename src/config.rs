@@ -0,0 +1,412 @@
+//! Declarative deck configuration
+//!
+//! A [Config] describes, per device serial, the display brightness and a set of
+//! pages where each key has an icon, label, background colour and action.
+//! Configs are loaded from TOML or YAML and converted to a [PageStack] for
+//! running the device (see the `streamdeck-daemon` binary).
+//!
+//! ```toml
+//! [[devices]]
+//! serial = "AL12K1C01234"
+//! brightness = 60
+//!
+//! [[devices.pages]]
+//! name = "main"
+//! keys = [
+//!     { key = 0, icon = "icons/media.png", text = "Media", folder = "media" },
+//!     { key = 1, colour = "firebrick", text = "Lock", command = "loginctl lock-session" },
+//! ]
+//!
+//! [[devices.pages]]
+//! name = "media"
+//! keys = [
+//!     { key = 1, icon = "icons/play.svg", command = "playerctl play-pause" },
+//! ]
+//! ```
+
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::face::{KeyFace, LabelPosition};
+use crate::images::{decode_image, Colour};
+use crate::info::Kind;
+use crate::pages::{Action, Page, PageStack};
+use crate::Error;
+
+/// Deck configuration for one or more devices
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub devices: Vec<DeviceConfig>,
+}
+
+/// Configuration for a single device
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeviceConfig {
+    /// Device serial, a config without a serial applies to any device
+    #[serde(default)]
+    pub serial: Option<String>,
+
+    /// Display brightness (0-100)
+    #[serde(default)]
+    pub brightness: Option<u8>,
+
+    /// Name of the page shown at startup, defaults to the first page
+    #[serde(default)]
+    pub root: Option<String>,
+
+    /// Key reserved for returning to the previous page on sub-pages
+    #[serde(default)]
+    pub back_key: u8,
+
+    pub pages: Vec<PageConfig>,
+}
+
+/// Named page of keys
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PageConfig {
+    pub name: String,
+
+    #[serde(default)]
+    pub keys: Vec<KeyConfig>,
+}
+
+/// Key appearance and action
+///
+/// At most one of `folder`, `back` and `command` may be set.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeyConfig {
+    /// Key index
+    pub key: u8,
+
+    /// Icon image file (relative paths are resolved against the config file)
+    pub icon: Option<String>,
+
+    /// Label text (requires the `default-font` feature)
+    pub text: Option<String>,
+
+    /// Label position, `top` or `bottom` (the default)
+    pub text_position: Option<String>,
+
+    /// Background colour (hex, CSS name or hsl)
+    pub colour: Option<String>,
+
+    /// Open the named page when pressed
+    pub folder: Option<String>,
+
+    /// Return to the previous page when pressed
+    pub back: bool,
+
+    /// Shell command to run when pressed
+    pub command: Option<String>,
+}
+
+impl Config {
+    /// Load a config file, using the extension to select TOML or YAML
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&data),
+            _ => Self::from_toml(&data),
+        }
+    }
+
+    /// Parse a TOML config
+    pub fn from_toml(data: &str) -> Result<Self, Error> {
+        toml::from_str(data).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// Parse a YAML config
+    pub fn from_yaml(data: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(data).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    /// Find the config for a device serial, falling back to a config without a serial
+    pub fn device(&self, serial: &str) -> Option<&DeviceConfig> {
+        self.devices
+            .iter()
+            .find(|d| d.serial.as_deref() == Some(serial))
+            .or_else(|| self.devices.iter().find(|d| d.serial.is_none()))
+    }
+}
+
+impl DeviceConfig {
+    /// Check the config is valid for a device of the provided kind
+    ///
+    /// All problems are reported as [Error::Config], naming the page and key.
+    pub fn validate(&self, kind: Kind) -> Result<(), Error> {
+        let invalid = |m: String| Err(Error::Config(m));
+
+        if let Some(b) = self.brightness {
+            if b > 100 {
+                return invalid(format!("brightness {} is above 100", b));
+            }
+        }
+        if self.back_key >= kind.keys() {
+            return invalid(format!("back key {} is out of range for {:?} ({} keys)", self.back_key, kind, kind.keys()));
+        }
+        if self.pages.is_empty() {
+            return invalid("no pages defined".to_string());
+        }
+        if let Some(root) = &self.root {
+            if !self.pages.iter().any(|p| &p.name == root) {
+                return invalid(format!("root page '{}' is not defined", root));
+            }
+        }
+
+        for (i, p) in self.pages.iter().enumerate() {
+            if self.pages[..i].iter().any(|o| o.name == p.name) {
+                return invalid(format!("page '{}' is defined more than once", p.name));
+            }
+
+            // The back key replaces any binding on pages opened as folders
+            let root = self.root.as_ref().unwrap_or(&self.pages[0].name);
+            let is_folder = self.pages.iter().flat_map(|o| &o.keys).any(|k| k.folder.as_ref() == Some(&p.name));
+            let sub_page = &p.name != root || is_folder;
+
+            for (j, k) in p.keys.iter().enumerate() {
+                let context = format!("page '{}' key {}", p.name, k.key);
+                if sub_page && k.key == self.back_key {
+                    return invalid(format!("{}: hidden by the back key on sub-pages", context));
+                }
+                if k.key >= kind.keys() {
+                    return invalid(format!("{}: out of range for {:?} ({} keys)", context, kind, kind.keys()));
+                }
+                if p.keys[..j].iter().any(|o| o.key == k.key) {
+                    return invalid(format!("{}: defined more than once", context));
+                }
+                if let Action::Folder(folder) = k.action().map_err(|e| Error::Config(format!("{}: {}", context, e)))? {
+                    if !self.pages.iter().any(|p| p.name == folder) {
+                        return invalid(format!("{}: folder page '{}' is not defined", context, folder));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Build a page stack for a device of the provided kind
+    ///
+    /// The config is validated first, icons are loaded relative to `base`,
+    /// and commands are returned as actions.
+    pub fn page_stack(&self, kind: Kind, base: &Path) -> Result<PageStack<String>, Error> {
+        self.validate(kind)?;
+
+        let root = self.root.as_ref().unwrap_or(&self.pages[0].name);
+
+        let mut pages = vec![];
+        for p in &self.pages {
+            let mut page = Page::new(&p.name);
+            for k in &p.keys {
+                let face = k
+                    .face(kind, base)
+                    .map_err(|e| Error::Config(format!("page '{}' key {}: {}", p.name, k.key, e)))?;
+                page = page.key(k.key, face, k.action()?);
+            }
+            pages.push(page);
+        }
+
+        // Validated above
+        let index = pages.iter().position(|p| p.name() == root).unwrap();
+
+        let mut stack = PageStack::new(pages.remove(index), self.back_key);
+        for page in pages {
+            stack.add_page(page);
+        }
+
+        Ok(stack)
+    }
+}
+
+impl KeyConfig {
+    /// Action bound to the key, setting more than one action is an error
+    pub fn action(&self) -> Result<Action<String>, Error> {
+        let set = self.folder.is_some() as u8 + self.back as u8 + self.command.is_some() as u8;
+        if set > 1 {
+            return Err(Error::Config("only one of folder, back and command may be set".to_string()));
+        }
+
+        let action = if let Some(folder) = &self.folder {
+            Action::Folder(folder.clone())
+        } else if self.back {
+            Action::Back
+        } else if let Some(command) = &self.command {
+            Action::Custom(command.clone())
+        } else {
+            Action::None
+        };
+        Ok(action)
+    }
+
+    /// Build the key face, loading the icon relative to `base`
+    pub fn face(&self, kind: Kind, base: &Path) -> Result<KeyFace, Error> {
+        let mut face = KeyFace::new();
+
+        if let Some(colour) = &self.colour {
+            face = face.background(Colour::from_str(colour).map_err(Error::Config)?);
+        }
+
+        if let Some(icon) = &self.icon {
+            let (w, h) = kind.image_size();
+            let path = base.join(icon);
+            let image = std::fs::read(&path)
+                .map_err(Error::from)
+                .and_then(|data| decode_image(&data, w, h))
+                .map_err(|e| Error::Config(format!("icon {}: {}", path.display(), e)))?;
+            face = face.icon(image);
+        }
+
+        #[cfg(not(feature = "default-font"))]
+        if self.text.is_some() {
+            return Err(Error::Config("text labels require the default-font feature".to_string()));
+        }
+
+        if let Some(text) = &self.text {
+            let position = match self.text_position.as_deref() {
+                None | Some("bottom") => LabelPosition::Bottom,
+                Some("top") => LabelPosition::Top,
+                Some(p) => return Err(Error::Config(format!("invalid text position: {}", p))),
+            };
+
+            #[cfg(feature = "default-font")]
+            {
+                face = face.font(crate::fonts::default_font());
+            }
+            face = face.label(text, position);
+        }
+
+        Ok(face)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pages::Press;
+
+    #[test]
+    fn load_toml_and_yaml() {
+        let toml = r##"
+            [[devices]]
+            serial = "ABC123"
+            brightness = 40
+            back_key = 5
+
+            [[devices.pages]]
+            name = "main"
+            keys = [
+                { key = 0, colour = "steelblue", folder = "media" },
+                { key = 1, command = "echo hello" },
+            ]
+
+            [[devices.pages]]
+            name = "media"
+            keys = [ { key = 1, colour = "#00ff00", command = "playerctl play-pause" } ]
+        "##;
+
+        let yaml = r##"
+            devices:
+              - serial: ABC123
+                brightness: 40
+                back_key: 5
+                pages:
+                  - name: main
+                    keys:
+                      - { key: 0, colour: steelblue, folder: media }
+                      - { key: 1, command: echo hello }
+                  - name: media
+                    keys:
+                      - { key: 1, colour: "#00ff00", command: playerctl play-pause }
+        "##;
+
+        let config = Config::from_toml(toml).unwrap();
+        assert_eq!(config, Config::from_yaml(yaml).unwrap());
+
+        assert!(config.device("other").is_none());
+        let device = config.device("ABC123").unwrap();
+        assert_eq!(device.brightness, Some(40));
+
+        let mut stack = device.page_stack(Kind::Original, Path::new(".")).unwrap();
+        assert_eq!(stack.press(1).unwrap(), Press::Action("echo hello".to_string()));
+        assert_eq!(stack.press(0).unwrap(), Press::Navigated);
+        assert_eq!(stack.press(1).unwrap(), Press::Action("playerctl play-pause".to_string()));
+        assert_eq!(stack.press(5).unwrap(), Press::Navigated);
+        assert_eq!(stack.current().name(), "main");
+    }
+
+    #[test]
+    fn reject_invalid() {
+        let valid = r##"
+            [[devices]]
+            back_key = 5
+
+            [[devices.pages]]
+            name = "main"
+            keys = [ { key = 0, folder = "media" } ]
+
+            [[devices.pages]]
+            name = "media"
+        "##;
+        let device = || Config::from_toml(valid).unwrap().devices.remove(0);
+        let rejected = |d: DeviceConfig| matches!(d.page_stack(Kind::Original, Path::new(".")), Err(Error::Config(_)));
+
+        assert!(device().validate(Kind::Original).is_ok());
+
+        let mut d = device();
+        d.pages[0].keys[0].command = Some("echo".to_string());
+        assert!(rejected(d), "multiple actions");
+
+        let mut d = device();
+        d.back_key = 15;
+        assert!(rejected(d), "back key out of range");
+
+        let mut d = device();
+        d.brightness = Some(101);
+        assert!(rejected(d), "brightness above 100");
+
+        let mut d = device();
+        d.pages[1].name = "main".to_string();
+        assert!(rejected(d), "duplicate page names");
+
+        let mut d = device();
+        d.pages[0].keys.push(KeyConfig { key: 0, ..Default::default() });
+        assert!(rejected(d), "duplicate keys");
+
+        let mut d = device();
+        d.pages[0].keys[0].key = 15;
+        assert!(rejected(d), "key out of range");
+
+        let mut d = device();
+        d.pages[0].keys[0].folder = Some("missing".to_string());
+        assert!(rejected(d), "unknown folder");
+
+        let mut d = device();
+        d.root = Some("missing".to_string());
+        assert!(rejected(d), "unknown root");
+
+        let mut d = device();
+        d.pages[1].keys.push(KeyConfig { key: 5, ..Default::default() });
+        assert!(rejected(d), "sub-page key hidden by the back key");
+
+        let mut d = device();
+        d.pages[0].keys.push(KeyConfig { key: 5, ..Default::default() });
+        assert!(d.validate(Kind::Original).is_ok(), "back key is free on the root page");
+
+        let mut d = device();
+        d.pages[0].keys[0].text = Some("Media".to_string());
+        assert_eq!(rejected(d), cfg!(not(feature = "default-font")), "text without a font");
+
+        let mut d = device();
+        d.pages[0].keys[0].icon = Some("missing.png".to_string());
+        match d.page_stack(Kind::Original, Path::new("icons")) {
+            Err(Error::Config(e)) => assert!(e.contains("page 'main' key 0") && e.contains("missing.png"), "{}", e),
+            _ => panic!("missing icon accepted"),
+        }
+    }
+}
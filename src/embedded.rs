@@ -0,0 +1,140 @@
+//! [embedded-graphics](https://docs.rs/embedded-graphics) draw targets
+//!
+//! [KeyCanvas] and [LcdCanvas] buffer drawing operations on the host, and are
+//! written to the device with an explicit `flush()` using the normal image
+//! pipeline, so embedded-graphics primitives, fonts and widgets can be used
+//! to draw keys and the LCD.
+
+use std::convert::Infallible;
+
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+use embedded_graphics_core::prelude::{DrawTarget, OriginDimensions, Pixel, Size};
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::{Error, StreamDeck};
+
+/// Draw target for a single key
+pub struct KeyCanvas {
+    key: u8,
+    image: RgbImage,
+}
+
+impl KeyCanvas {
+    /// Create a canvas for a key, initialised from the current key contents (or black)
+    pub fn new(deck: &StreamDeck, key: u8) -> Result<Self, Error> {
+        if key >= deck.kind().keys() {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        let (w, h) = deck.image_size();
+        let image = match deck.framebuffer().key(key) {
+            Some(k) => k.clone(),
+            None => RgbImage::new(w as u32, h as u32),
+        };
+
+        Ok(Self { key, image })
+    }
+
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    /// Fetch the drawn image
+    pub fn image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// Write the drawn image to the key
+    pub fn flush(&self, deck: &mut StreamDeck) -> Result<(), Error> {
+        deck.set_button_image(self.key, DynamicImage::ImageRgb8(self.image.clone()))
+    }
+}
+
+/// Draw target for the LCD strip (Stream Deck Plus)
+pub struct LcdCanvas {
+    image: RgbImage,
+}
+
+impl LcdCanvas {
+    /// Create a canvas for the LCD, initialised from the current LCD contents
+    pub fn new(deck: &StreamDeck) -> Result<Self, Error> {
+        let image = deck.framebuffer().lcd().ok_or(Error::NoLcd)?.clone();
+        Ok(Self { image })
+    }
+
+    /// Fetch the drawn image
+    pub fn image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// Write the drawn image to the LCD
+    pub fn flush(&self, deck: &mut StreamDeck) -> Result<(), Error> {
+        let (w, h) = self.image.dimensions();
+        deck.write_lcd_raw(0, 0, w as u16, h as u16, self.image.as_raw())
+    }
+}
+
+/// Draw pixels into an image, discarding those out of bounds
+fn draw_pixels<I: IntoIterator<Item = Pixel<Rgb888>>>(image: &mut RgbImage, pixels: I) {
+    let (w, h) = image.dimensions();
+
+    for Pixel(p, c) in pixels {
+        if p.x >= 0 && p.y >= 0 && (p.x as u32) < w && (p.y as u32) < h {
+            image.put_pixel(p.x as u32, p.y as u32, Rgb([c.r(), c.g(), c.b()]));
+        }
+    }
+}
+
+impl OriginDimensions for KeyCanvas {
+    fn size(&self) -> Size {
+        Size::new(self.image.width(), self.image.height())
+    }
+}
+
+impl DrawTarget for KeyCanvas {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Self::Color>>>(&mut self, pixels: I) -> Result<(), Self::Error> {
+        draw_pixels(&mut self.image, pixels);
+        Ok(())
+    }
+}
+
+impl OriginDimensions for LcdCanvas {
+    fn size(&self) -> Size {
+        Size::new(self.image.width(), self.image.height())
+    }
+}
+
+impl DrawTarget for LcdCanvas {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Self::Color>>>(&mut self, pixels: I) -> Result<(), Self::Error> {
+        draw_pixels(&mut self.image, pixels);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_graphics_core::prelude::Point;
+
+    #[test]
+    fn draw_key_pixels() {
+        let mut canvas = KeyCanvas {
+            key: 0,
+            image: RgbImage::new(72, 72),
+        };
+
+        canvas.clear(Rgb888::BLUE).unwrap();
+        canvas
+            .draw_iter([Pixel(Point::new(1, 2), Rgb888::RED), Pixel(Point::new(-1, 80), Rgb888::RED)])
+            .unwrap();
+
+        assert_eq!(canvas.image().get_pixel(1, 2).0, [255, 0, 0]);
+        assert_eq!(canvas.image().get_pixel(0, 0).0, [0, 0, 255]);
+    }
+}
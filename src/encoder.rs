@@ -0,0 +1,361 @@
+//! Pluggable JPEG encoding for V2 devices
+//!
+//! Devices using [ImageMode::Jpeg](crate::ImageMode) accept key and LCD
+//! images as JPEG data split over fixed size reports. The [ImageEncoder]
+//! trait allows the JPEG backend to be replaced, while [EncodeOptions]
+//! controls quality, chroma subsampling and an optional report budget.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, ImageFormat};
+use tracing::{debug, trace};
+
+use crate::images::{load_image, ImageOptions};
+use crate::info::{ImageMode, Kind};
+use crate::Error;
+
+/// JPEG chroma subsampling mode
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma
+    Yuv444,
+    /// Chroma halved horizontally
+    Yuv422,
+    /// Chroma halved horizontally and vertically
+    Yuv420,
+}
+
+/// Options controlling JPEG encoding
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct EncodeOptions {
+    /// JPEG quality (1-100), or the upper bound when `max_reports` is set
+    pub quality: u8,
+
+    /// Chroma subsampling
+    ///
+    /// Only honoured by the `jpeg-encoder` backend, [ImageJpegEncoder] always
+    /// uses the `image` crate default regardless of this setting.
+    pub subsampling: ChromaSubsampling,
+
+    /// Select the highest quality that fits in this many reports
+    pub max_reports: Option<usize>,
+}
+
+impl EncodeOptions {
+    pub fn new(quality: u8, subsampling: ChromaSubsampling, max_reports: Option<usize>) -> Self {
+        Self {
+            quality,
+            subsampling,
+            max_reports,
+        }
+    }
+}
+
+impl Default for EncodeOptions {
+    /// default is full quality with no size budget, matching earlier releases
+    fn default() -> Self {
+        Self {
+            quality: 100,
+            subsampling: ChromaSubsampling::Yuv420,
+            max_reports: None,
+        }
+    }
+}
+
+/// Backend for encoding RGB bitmaps into JPEG images
+pub trait ImageEncoder: Send + Sync {
+    /// Encode an RGB8 bitmap with the provided quality and subsampling
+    fn encode(
+        &self,
+        image: &[u8],
+        width: usize,
+        height: usize,
+        quality: u8,
+        subsampling: ChromaSubsampling,
+    ) -> Result<Vec<u8>, Error>;
+}
+
+/// JPEG encoder using the `image` crate
+///
+/// This backend does not support configurable chroma subsampling, the
+/// `subsampling` argument (and [EncodeOptions::subsampling]) is ignored.
+/// Enable the `jpeg-encoder` feature and use `FastJpegEncoder` to control it.
+#[derive(Debug, Default, Clone)]
+pub struct ImageJpegEncoder;
+
+impl ImageEncoder for ImageJpegEncoder {
+    fn encode(
+        &self,
+        image: &[u8],
+        width: usize,
+        height: usize,
+        quality: u8,
+        _subsampling: ChromaSubsampling,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+        encoder.encode(image, width as u32, height as u32, ColorType::Rgb8)?;
+        Ok(buf)
+    }
+}
+
+/// Faster JPEG encoder using the `jpeg-encoder` crate
+#[cfg(feature = "jpeg-encoder")]
+#[derive(Debug, Default, Clone)]
+pub struct FastJpegEncoder;
+
+#[cfg(feature = "jpeg-encoder")]
+impl ImageEncoder for FastJpegEncoder {
+    fn encode(
+        &self,
+        image: &[u8],
+        width: usize,
+        height: usize,
+        quality: u8,
+        subsampling: ChromaSubsampling,
+    ) -> Result<Vec<u8>, Error> {
+        use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+        let mut buf = Vec::new();
+        let mut encoder = Encoder::new(&mut buf, quality);
+        encoder.set_sampling_factor(match subsampling {
+            ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+        });
+        encoder.encode(image, width as u16, height as u16, ColorType::Rgb)?;
+        Ok(buf)
+    }
+}
+
+/// Encode an RGB8 bitmap using the provided encoder and options
+///
+/// When `opts.max_reports` is set, the highest quality up to `opts.quality` that fits
+/// in `max_reports * report_payload` bytes is selected. If no quality fits the
+/// lowest quality image is returned.
+pub fn encode_with(
+    encoder: &dyn ImageEncoder,
+    image: &[u8],
+    width: usize,
+    height: usize,
+    opts: &EncodeOptions,
+    report_payload: usize,
+) -> Result<Vec<u8>, Error> {
+    let quality = opts.quality.clamp(1, 100);
+
+    let budget = match opts.max_reports {
+        Some(n) => n * report_payload,
+        None => return encoder.encode(image, width, height, quality, opts.subsampling),
+    };
+
+    // Output size grows monotonically (near enough) with quality, so binary search
+    // for the highest quality within the budget
+    let (mut lo, mut hi) = (1u8, quality);
+    let mut best = None;
+
+    while lo <= hi {
+        let q = lo + (hi - lo) / 2;
+        let data = encoder.encode(image, width, height, q, opts.subsampling)?;
+        trace!("quality {} encoded to {} bytes (budget {})", q, data.len(), budget);
+
+        if data.len() <= budget {
+            best = Some(data);
+            lo = q + 1;
+        } else if q == 1 {
+            break;
+        } else {
+            hi = q - 1;
+        }
+    }
+
+    match best {
+        Some(data) => Ok(data),
+        None => {
+            debug!("no quality fits in {} bytes, using minimum quality", budget);
+            encoder.encode(image, width, height, 1, opts.subsampling)
+        }
+    }
+}
+
+/// Read the width and height from JPEG header data
+///
+/// This scans markers up to the start of frame without decoding the image.
+pub fn jpeg_dimensions(data: &[u8]) -> Result<(usize, usize), Error> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err(Error::InvalidJpeg);
+    }
+
+    let mut i = 2;
+    while i + 1 < data.len() {
+        if data[i] != 0xff {
+            return Err(Error::InvalidJpeg);
+        }
+        let marker = data[i + 1];
+        i += 2;
+
+        match marker {
+            // Fill bytes
+            0xff => i -= 1,
+            // Markers without a length
+            0x01 | 0xd0..=0xd7 => (),
+            // Start of frame (excluding DHT, JPG and DAC which share the range)
+            0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                let sof = data.get(i..i + 7).ok_or(Error::InvalidJpeg)?;
+                let height = u16::from_be_bytes([sof[3], sof[4]]) as usize;
+                let width = u16::from_be_bytes([sof[5], sof[6]]) as usize;
+                return Ok((width, height));
+            }
+            // Start of scan or end of image before a frame header
+            0xda | 0xd9 => return Err(Error::InvalidJpeg),
+            _ => {
+                let len = data.get(i..i + 2).ok_or(Error::InvalidJpeg)?;
+                i += u16::from_be_bytes([len[0], len[1]]) as usize;
+            }
+        }
+    }
+
+    Err(Error::InvalidJpeg)
+}
+
+/// Pre-encode a directory of icons for devices of the provided kind
+///
+/// Each image in `src` is loaded with the provided [ImageOptions], transformed
+/// for the device and encoded to `dest` with the same file stem and a `.jpg`
+/// extension, ready to be written with
+/// [StreamDeck::set_button_jpeg](crate::StreamDeck::set_button_jpeg).
+/// Colour profiles are not applied. Inputs that would be encoded to the same
+/// file (`a.png` and `a.svg`) are rejected before anything is written. Returns
+/// the paths of the encoded files.
+pub fn prebake_dir<S: AsRef<Path>, D: AsRef<Path>>(
+    kind: Kind,
+    src: S,
+    dest: D,
+    opts: &ImageOptions,
+    encoder: &dyn ImageEncoder,
+    encode_opts: &EncodeOptions,
+) -> Result<Vec<PathBuf>, Error> {
+    if kind.image_mode() != ImageMode::Jpeg {
+        return Err(Error::UnsupportedImageMode);
+    }
+
+    let (w, h) = kind.image_size();
+    let payload = kind.image_report_len() - kind.image_report_header_len();
+    std::fs::create_dir_all(dest.as_ref())?;
+
+    let mut inputs = vec![];
+    for entry in std::fs::read_dir(src)? {
+        let path = entry?.path();
+
+        let is_image = ImageFormat::from_path(&path).is_ok()
+            || path.extension().map(|e| e.eq_ignore_ascii_case("svg")).unwrap_or(false);
+        if path.is_file() && is_image {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    // Outputs are named by stem, so check for collisions before encoding
+    let mut outputs = HashSet::new();
+    for path in &inputs {
+        let out = prebaked_path(dest.as_ref(), path);
+        if outputs.contains(&out) {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("multiple inputs would be prebaked to {}", out.display()),
+            )));
+        }
+        outputs.insert(out);
+    }
+
+    let mut baked = vec![];
+    for path in inputs {
+        let image = load_image(
+            &path,
+            w,
+            h,
+            kind.image_rotation(),
+            kind.image_mirror(),
+            opts,
+            kind.image_colour_order(),
+        )?;
+        let jpeg = encode_with(encoder, &image, w, h, encode_opts, payload)?;
+
+        let out = prebaked_path(dest.as_ref(), &path);
+        debug!("prebaked {} to {} ({} bytes)", path.display(), out.display(), jpeg.len());
+        std::fs::write(&out, jpeg)?;
+        baked.push(out);
+    }
+
+    Ok(baked)
+}
+
+/// Output path for a prebaked image, appending `.jpg` to the full file stem
+fn prebaked_path(dest: &Path, src: &Path) -> PathBuf {
+    let mut name = src.file_stem().unwrap_or_default().to_os_string();
+    name.push(".jpg");
+    dest.join(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_image(w: usize, h: usize) -> Vec<u8> {
+        (0..w * h * 3).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn encode_within_budget() {
+        let image = test_image(72, 72);
+        let opts = EncodeOptions::new(100, ChromaSubsampling::Yuv420, Some(2));
+
+        let full = encode_with(&ImageJpegEncoder, &image, 72, 72, &EncodeOptions::default(), 1016)
+            .unwrap();
+        let budgeted = encode_with(&ImageJpegEncoder, &image, 72, 72, &opts, 1016).unwrap();
+
+        assert!(full.len() > 2 * 1016);
+        assert!(budgeted.len() <= 2 * 1016);
+    }
+
+    #[test]
+    fn parse_jpeg_dimensions() {
+        let image = test_image(96, 48);
+        let jpeg = ImageJpegEncoder.encode(&image, 96, 48, 80, ChromaSubsampling::Yuv420).unwrap();
+
+        assert_eq!(jpeg_dimensions(&jpeg).unwrap(), (96, 48));
+        assert!(jpeg_dimensions(&jpeg[..20]).is_err());
+        assert!(jpeg_dimensions(b"BM not a jpeg").is_err());
+    }
+
+    #[test]
+    fn prebake_collisions() {
+        let dir = std::env::temp_dir().join("streamdeck-prebake-test");
+        let (src, dest) = (dir.join("src"), dir.join("dest"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::copy("./icons/power.png", src.join("power.png")).unwrap();
+
+        let opts = ImageOptions::default();
+        let baked = prebake_dir(Kind::OriginalV2, &src, &dest, &opts, &ImageJpegEncoder, &EncodeOptions::default()).unwrap();
+        assert_eq!(baked, vec![dest.join("power.jpg")]);
+
+        // A second image with the same stem would overwrite the first
+        image::open("./icons/power.png").unwrap().save(src.join("power.bmp")).unwrap();
+        assert!(prebake_dir(Kind::OriginalV2, &src, &dest, &opts, &ImageJpegEncoder, &EncodeOptions::default()).is_err());
+        std::fs::remove_file(src.join("power.bmp")).unwrap();
+
+        // Dotted stems keep everything before the extension
+        std::fs::copy("./icons/power.png", src.join("power.v2.png")).unwrap();
+        let baked = prebake_dir(Kind::OriginalV2, &src, &dest, &opts, &ImageJpegEncoder, &EncodeOptions::default()).unwrap();
+        assert_eq!(baked, vec![dest.join("power.jpg"), dest.join("power.v2.jpg")]);
+
+        std::fs::copy("./icons/power.png", src.join("power.v2.bmp")).unwrap();
+        assert!(prebake_dir(Kind::OriginalV2, &src, &dest, &opts, &ImageJpegEncoder, &EncodeOptions::default()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+//! Layered key faces
+//!
+//! A [KeyFace] stacks a background, an icon, a text label and a corner badge,
+//! and is rendered at the device image size before being written using the
+//! normal image pipeline (see [StreamDeck::set_button_face](crate::StreamDeck::set_button_face)).
+
+#[cfg(feature = "text")]
+use std::sync::Arc;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgb, RgbImage};
+use imageproc::drawing::draw_filled_circle_mut;
+
+use crate::images::{blend, rgb, Colour};
+#[cfg(feature = "text")]
+use crate::text::{self, HAlign, Scale, TextOptions, TextPosition, Typeface, VAlign};
+
+/// Key background fill
+#[derive(Debug, Clone)]
+pub enum Background {
+    /// Solid colour
+    Solid(Colour),
+    /// Gradient from the top colour to the bottom colour
+    VerticalGradient(Colour, Colour),
+    /// Gradient from the left colour to the right colour
+    HorizontalGradient(Colour, Colour),
+}
+
+/// Label placement on the key
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LabelPosition {
+    Top,
+    Bottom,
+}
+
+/// Badge content
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Badge {
+    /// Numeric count, shown as a dot where text is unavailable
+    Count(u32),
+    /// Plain dot
+    Dot,
+}
+
+/// Badge placement on the key
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Builder for a layered key image
+#[derive(Clone)]
+pub struct KeyFace {
+    background: Background,
+    icon: Option<DynamicImage>,
+    icon_padding: u32,
+    #[cfg(feature = "text")]
+    font: Option<Arc<dyn Typeface + Send + Sync>>,
+    label: Option<(String, LabelPosition)>,
+    label_colour: Colour,
+    label_size: f32,
+    badge: Option<(Badge, Corner)>,
+    badge_colour: Colour,
+}
+
+impl KeyFace {
+    /// Create a key face with a black background
+    pub fn new() -> Self {
+        Self {
+            background: Background::Solid(Colour::rgb(0, 0, 0)),
+            icon: None,
+            icon_padding: 8,
+            #[cfg(feature = "text")]
+            font: None,
+            label: None,
+            label_colour: Colour::rgb(255, 255, 255),
+            label_size: 14.0,
+            badge: None,
+            badge_colour: Colour::rgb(220, 30, 30),
+        }
+    }
+
+    /// Set a solid background colour
+    pub fn background(mut self, colour: Colour) -> Self {
+        self.background = Background::Solid(colour);
+        self
+    }
+
+    /// Set the background fill
+    pub fn fill(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// Set the icon, scaled to fit the key (less padding) preserving aspect ratio
+    pub fn icon(mut self, icon: DynamicImage) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    /// Set the padding around the icon in pixels
+    pub fn icon_padding(mut self, padding: u32) -> Self {
+        self.icon_padding = padding;
+        self
+    }
+
+    /// Set the font used for the label and badge count
+    #[cfg(feature = "text")]
+    pub fn font(mut self, font: impl Typeface + Send + Sync + 'static) -> Self {
+        self.font = Some(Arc::new(font));
+        self
+    }
+
+    /// Set a text label at the top or bottom of the key
+    ///
+    /// Labels are only drawn when a font is set.
+    pub fn label(mut self, text: &str, position: LabelPosition) -> Self {
+        self.label = Some((text.to_string(), position));
+        self
+    }
+
+    /// Set the label text colour
+    pub fn label_colour(mut self, colour: Colour) -> Self {
+        self.label_colour = colour;
+        self
+    }
+
+    /// Set the label text height in pixels
+    pub fn label_size(mut self, size: f32) -> Self {
+        self.label_size = size;
+        self
+    }
+
+    /// Set a badge in a corner of the key
+    pub fn badge(mut self, badge: Badge, corner: Corner) -> Self {
+        self.badge = Some((badge, corner));
+        self
+    }
+
+    /// Set the badge colour
+    pub fn badge_colour(mut self, colour: Colour) -> Self {
+        self.badge_colour = colour;
+        self
+    }
+
+    /// Render the key face to an image of the provided size
+    pub fn render(&self, width: u32, height: u32) -> RgbImage {
+        let mut image = RgbImage::new(width, height);
+        self.draw_background(&mut image);
+
+        // Reserve space for the label so it doesn't overlap the icon
+        let band = match &self.label {
+            Some((_, position)) if self.has_font() => {
+                Some((*position, (self.label_size * 1.2).ceil() as u32))
+            }
+            _ => None,
+        };
+
+        if let Some(icon) = &self.icon {
+            let (top, bottom) = match band {
+                Some((LabelPosition::Top, h)) => (h, 0),
+                Some((LabelPosition::Bottom, h)) => (0, h),
+                None => (0, 0),
+            };
+            let pad = self.icon_padding;
+            let area_w = width.saturating_sub(2 * pad);
+            let area_h = height.saturating_sub(2 * pad + top + bottom);
+
+            if area_w > 0 && area_h > 0 {
+                let icon = icon.resize(area_w, area_h, FilterType::Gaussian).to_rgba8();
+                let x0 = pad + (area_w - icon.width()) / 2;
+                let y0 = pad + top + (area_h - icon.height()) / 2;
+
+                for (x, y, p) in icon.enumerate_pixels() {
+                    blend_at(&mut image, x0 + x, y0 + y, [p.0[0], p.0[1], p.0[2]], p.0[3]);
+                }
+            }
+        }
+
+        #[cfg(feature = "text")]
+        if let (Some((label, position)), Some(font)) = (&self.label, &self.font) {
+            let pos = TextPosition::Aligned {
+                horizontal: HAlign::Centre,
+                vertical: match position {
+                    LabelPosition::Top => VAlign::Top,
+                    LabelPosition::Bottom => VAlign::Bottom,
+                },
+                padding: 2,
+            };
+            let opts = TextOptions::default()
+                .with_scale(Scale::uniform(self.label_size))
+                .with_shrink_to_fit(self.label_size / 2.0);
+            let opts = TextOptions {
+                foreground: self.label_colour.clone(),
+                ..opts
+            };
+            text::draw_text(&mut image, font.as_ref(), &pos, label, &opts);
+        }
+
+        if let Some((badge, corner)) = &self.badge {
+            self.draw_badge(&mut image, *badge, *corner);
+        }
+
+        image
+    }
+
+    #[cfg(feature = "text")]
+    fn has_font(&self) -> bool {
+        self.font.is_some()
+    }
+
+    #[cfg(not(feature = "text"))]
+    fn has_font(&self) -> bool {
+        false
+    }
+
+    fn draw_background(&self, image: &mut RgbImage) {
+        let (w, h) = image.dimensions();
+
+        for (x, y, p) in image.enumerate_pixels_mut() {
+            let c = match &self.background {
+                Background::Solid(c) => rgb(c),
+                Background::VerticalGradient(a, b) => lerp(a, b, y as f32 / (h.max(2) - 1) as f32),
+                Background::HorizontalGradient(a, b) => lerp(a, b, x as f32 / (w.max(2) - 1) as f32),
+            };
+            *p = c;
+        }
+    }
+
+    fn draw_badge(&self, image: &mut RgbImage, badge: Badge, corner: Corner) {
+        let (w, h) = image.dimensions();
+
+        // Counts need a larger badge to fit the text
+        let count = match badge {
+            Badge::Count(n) if self.has_font() => Some(n),
+            _ => None,
+        };
+        let radius = match count {
+            Some(_) => (w.min(h) / 6).max(4) as i32,
+            None => (w.min(h) / 12).max(2) as i32,
+        };
+
+        let inset = radius + 2;
+        let cx = match corner {
+            Corner::TopLeft | Corner::BottomLeft => inset,
+            Corner::TopRight | Corner::BottomRight => w as i32 - inset,
+        };
+        let cy = match corner {
+            Corner::TopLeft | Corner::TopRight => inset,
+            Corner::BottomLeft | Corner::BottomRight => h as i32 - inset,
+        };
+
+        draw_filled_circle_mut(image, (cx, cy), radius, rgb(&self.badge_colour));
+
+        #[cfg(feature = "text")]
+        if let (Some(n), Some(font)) = (count, &self.font) {
+            let label = if n > 99 { "99+".to_string() } else { n.to_string() };
+            let size = radius as f32 * 1.4;
+            let scale = Scale::uniform(size);
+            let tw = font.text_width(&label, scale);
+
+            font.draw_line(
+                image,
+                Rgb([255, 255, 255]),
+                (cx as f32 - tw / 2.0).round() as i32,
+                (cy as f32 - size / 2.0).round() as i32,
+                scale,
+                &label,
+            );
+        }
+    }
+}
+
+impl Default for KeyFace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lerp(a: &Colour, b: &Colour, t: f32) -> Rgb<u8> {
+    let l = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Rgb([l(a.r, b.r), l(a.g, b.g), l(a.b, b.b)])
+}
+
+fn blend_at(image: &mut RgbImage, x: u32, y: u32, c: [u8; 3], alpha: u8) {
+    if x < image.width() && y < image.height() {
+        blend(image.get_pixel_mut(x, y), c, alpha);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_layers() {
+        let icon = DynamicImage::ImageRgb8(RgbImage::from_pixel(10, 10, Rgb([0, 255, 0])));
+        let face = KeyFace::new()
+            .fill(Background::VerticalGradient(
+                Colour::rgb(0, 0, 0),
+                Colour::rgb(0, 0, 255),
+            ))
+            .icon(icon)
+            .badge(Badge::Dot, Corner::TopRight);
+
+        let image = face.render(72, 72);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(0, 71).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(36, 36).0, [0, 255, 0]);
+        assert_eq!(image.get_pixel(72 - 8, 8).0, [220, 30, 30]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Font discovery and loading
+//!
+//! [FontLibrary] (with the `fonts` feature) looks up installed fonts by family,
+//! weight and style, caching loaded faces. The `default-font` feature bundles a
+//! small public domain font (Tuffy) so text can be rendered without any fonts
+//! installed.
+
+#[cfg(feature = "fonts")]
+use std::collections::HashMap;
+
+#[cfg(any(feature = "default-font", feature = "fonts"))]
+use rusttype::Font;
+
+#[cfg(all(feature = "fonts", feature = "shaping"))]
+use crate::shaping::ShapedFont;
+
+/// Bundled default font data (Tuffy, public domain)
+#[cfg(feature = "default-font")]
+pub const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/Tuffy.ttf");
+
+/// Load the bundled default font
+#[cfg(feature = "default-font")]
+pub fn default_font() -> Font<'static> {
+    Font::try_from_bytes(DEFAULT_FONT).unwrap()
+}
+
+/// Font style for lookups
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// Weight of a regular font
+pub const WEIGHT_NORMAL: u16 = 400;
+
+/// Weight of a bold font
+pub const WEIGHT_BOLD: u16 = 700;
+
+/// Library of fonts searchable by family name
+///
+/// Family names may also be the generic `serif`, `sans-serif` or `monospace` families.
+#[cfg(feature = "fonts")]
+pub struct FontLibrary {
+    db: fontdb::Database,
+    cache: HashMap<fontdb::ID, Font<'static>>,
+    #[cfg(feature = "shaping")]
+    shaped: HashMap<fontdb::ID, ShapedFont>,
+}
+
+#[cfg(feature = "fonts")]
+impl FontLibrary {
+    /// Create an empty font library
+    pub fn new() -> Self {
+        Self {
+            db: fontdb::Database::new(),
+            cache: HashMap::new(),
+            #[cfg(feature = "shaping")]
+            shaped: HashMap::new(),
+        }
+    }
+
+    /// Create a font library containing installed system fonts
+    ///
+    /// On linux this scans the directories configured for fontconfig.
+    pub fn system() -> Self {
+        let mut library = Self::new();
+        library.db.load_system_fonts();
+        library
+    }
+
+    /// Add all fonts in a directory (recursively) to the library
+    pub fn load_dir<P: AsRef<std::path::Path>>(&mut self, dir: P) {
+        self.db.load_fonts_dir(dir);
+    }
+
+    /// Add font data to the library
+    pub fn load_font_data(&mut self, data: Vec<u8>) {
+        self.db.load_font_data(data);
+    }
+
+    /// List the font families in the library
+    pub fn families(&self) -> Vec<String> {
+        let mut families: Vec<_> = self
+            .db
+            .faces()
+            .filter_map(|f| f.families.first().map(|(name, _)| name.clone()))
+            .collect();
+        families.sort();
+        families.dedup();
+        families
+    }
+
+    /// Find the closest matching face for a family, weight and style
+    pub fn find(&mut self, family: &str, weight: u16, style: FontStyle) -> Option<Font<'static>> {
+        let id = self.query(family, weight, style)?;
+
+        if let Some(font) = self.cache.get(&id) {
+            return Some(font.clone());
+        }
+
+        let font = self
+            .db
+            .with_face_data(id, |data, index| Font::try_from_vec_and_index(data.to_vec(), index))??;
+        self.cache.insert(id, font.clone());
+
+        Some(font)
+    }
+
+    /// Find the closest matching face for shaped text rendering
+    #[cfg(feature = "shaping")]
+    pub fn find_shaped(&mut self, family: &str, weight: u16, style: FontStyle) -> Option<ShapedFont> {
+        let id = self.query(family, weight, style)?;
+
+        if let Some(font) = self.shaped.get(&id) {
+            return Some(font.clone());
+        }
+
+        let font = self
+            .db
+            .with_face_data(id, |data, index| ShapedFont::from_bytes_and_index(data.to_vec(), index))?
+            .ok()?;
+        self.shaped.insert(id, font.clone());
+
+        Some(font)
+    }
+
+    fn query(&self, family: &str, weight: u16, style: FontStyle) -> Option<fontdb::ID> {
+        let family = match family.to_lowercase().as_str() {
+            "serif" => fontdb::Family::Serif,
+            "sans-serif" | "sans" => fontdb::Family::SansSerif,
+            "monospace" | "mono" => fontdb::Family::Monospace,
+            _ => fontdb::Family::Name(family),
+        };
+
+        let style = match style {
+            FontStyle::Normal => fontdb::Style::Normal,
+            FontStyle::Italic => fontdb::Style::Italic,
+            FontStyle::Oblique => fontdb::Style::Oblique,
+        };
+
+        self.db.query(&fontdb::Query {
+            families: &[family],
+            weight: fontdb::Weight(weight),
+            style,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(feature = "fonts")]
+impl Default for FontLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "fonts", feature = "default-font"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_fonts() {
+        let mut library = FontLibrary::new();
+        library.load_font_data(DEFAULT_FONT.to_vec());
+
+        assert_eq!(library.families(), vec!["Tuffy".to_string()]);
+        assert!(library.find("Tuffy", WEIGHT_NORMAL, FontStyle::Normal).is_some());
+        assert!(library.find("Missing", WEIGHT_NORMAL, FontStyle::Normal).is_none());
+    }
+}
@@ -0,0 +1,217 @@
+//! Host side mirror of device images
+//!
+//! Devices can not be read back, so [StreamDeck](crate::StreamDeck) keeps a copy of
+//! each key and LCD image as written, in un-rotated RGB. Keys written with raw
+//! device data that can not be decoded are marked as unknown, and LCD areas
+//! that were never written or were written with pre-encoded data are tracked
+//! per pixel as unknown (and drawn black).
+
+use image::{imageops, DynamicImage, Rgb, RgbImage};
+
+use crate::images::apply_transform;
+use crate::info::{ColourOrder, Kind, Rotation};
+use crate::rgb_to_bgr;
+
+/// Background colour used for the device body in snapshots
+const BODY_COLOUR: Rgb<u8> = Rgb([24, 24, 24]);
+
+/// Host side copy of the images displayed on a device
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    kind: Kind,
+    keys: Vec<Option<RgbImage>>,
+    lcd: Option<RgbImage>,
+    // Per pixel flags for LCD contents matching the device, row major
+    lcd_known: Vec<bool>,
+}
+
+impl Framebuffer {
+    pub fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            keys: vec![None; kind.keys() as usize],
+            lcd: kind
+                .lcd_size()
+                .map(|(w, h)| RgbImage::new(w as u32, h as u32)),
+            lcd_known: vec![false; kind.lcd_size().map(|(w, h)| w * h).unwrap_or(0)],
+        }
+    }
+
+    /// Fetch the image last written to a key, if known
+    pub fn key(&self, key: u8) -> Option<&RgbImage> {
+        self.keys.get(key as usize).and_then(|k| k.as_ref())
+    }
+
+    /// Fetch the LCD contents, for devices with an LCD
+    ///
+    /// Unknown areas are black, see [Framebuffer::lcd_known].
+    pub fn lcd(&self) -> Option<&RgbImage> {
+        self.lcd.as_ref()
+    }
+
+    /// Check whether an LCD pixel is known to match the device
+    pub fn lcd_known(&self, x: u32, y: u32) -> bool {
+        match &self.lcd {
+            Some(lcd) if x < lcd.width() && y < lcd.height() => self.lcd_known[(y * lcd.width() + x) as usize],
+            _ => false,
+        }
+    }
+
+    /// Per pixel LCD known flags, row major
+    pub(crate) fn lcd_known_mask(&self) -> &[bool] {
+        &self.lcd_known
+    }
+
+    fn set_lcd_known(&mut self, x: u32, y: u32, w: u32, h: u32, known: bool) {
+        let (lw, lh) = match &self.lcd {
+            Some(lcd) => lcd.dimensions(),
+            None => return,
+        };
+        for py in y..(y + h).min(lh) {
+            for px in x..(x + w).min(lw) {
+                self.lcd_known[(py * lw + px) as usize] = known;
+            }
+        }
+    }
+
+    pub(crate) fn set_key(&mut self, key: u8, image: RgbImage) {
+        if let Some(k) = self.keys.get_mut(key as usize) {
+            *k = Some(image);
+        }
+    }
+
+    /// Record a key image from device native pixel data
+    pub(crate) fn set_key_native(&mut self, key: u8, mut data: Vec<u8>) {
+        let (w, h) = self.kind.image_size();
+
+        if matches!(self.kind.image_colour_order(), ColourOrder::BGR) {
+            rgb_to_bgr(&mut data);
+        }
+
+        let image = match RgbImage::from_raw(w as u32, h as u32, data) {
+            Some(i) => i,
+            None => return self.invalidate_key(key),
+        };
+
+        // Mirroring is its own inverse, rotation is undone in the opposite direction
+        let image = apply_transform(DynamicImage::ImageRgb8(image), Rotation::Rot0, self.kind.image_mirror());
+        let image = match self.kind.image_rotation() {
+            Rotation::Rot0 => image,
+            Rotation::Rot90 => image.rotate270(),
+            Rotation::Rot180 => image.rotate180(),
+            Rotation::Rot270 => image.rotate90(),
+        };
+
+        self.set_key(key, image.into_rgb8());
+    }
+
+    pub(crate) fn invalidate_key(&mut self, key: u8) {
+        if let Some(k) = self.keys.get_mut(key as usize) {
+            *k = None;
+        }
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        self.keys.iter_mut().for_each(|k| *k = None);
+        if let Some(lcd) = &mut self.lcd {
+            lcd.pixels_mut().for_each(|p| *p = Rgb([0, 0, 0]));
+        }
+        self.lcd_known.iter_mut().for_each(|k| *k = false);
+    }
+
+    pub(crate) fn invalidate_lcd_region(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        if let Some(lcd) = &mut self.lcd {
+            let black = RgbImage::new(w, h);
+            imageops::replace(lcd, &black, x as i64, y as i64);
+        }
+        self.set_lcd_known(x, y, w, h, false);
+    }
+
+    pub(crate) fn set_lcd_region(&mut self, x: u32, y: u32, image: &RgbImage) {
+        if let Some(lcd) = &mut self.lcd {
+            imageops::replace(lcd, image, x as i64, y as i64);
+        }
+        self.set_lcd_known(x, y, image.width(), image.height(), true);
+    }
+
+    /// Render the whole deck as it physically appears
+    ///
+    /// Keys are laid out in rows with approximate physical spacing
+    /// (see [Kind::key_gap]), with the LCD (if any) below the keys.
+    /// Keys with unknown contents are drawn black.
+    pub fn snapshot(&self) -> RgbImage {
+        let (kw, kh) = self.kind.image_size();
+        let (kw, kh) = (kw as u32, kh as u32);
+        let gap = self.kind.key_gap() as u32;
+        let cols = self.kind.key_columns() as u32;
+        let rows = (self.kind.keys() as u32).div_ceil(cols);
+
+        let keys_w = cols * kw + (cols - 1) * gap;
+        let keys_h = rows * kh + (rows - 1) * gap;
+        let (lcd_w, lcd_h) = self.lcd.as_ref().map(|l| l.dimensions()).unwrap_or((0, 0));
+
+        let width = keys_w.max(lcd_w) + 2 * gap;
+        let height = keys_h + if lcd_h > 0 { lcd_h + gap } else { 0 } + 2 * gap;
+        let mut image = RgbImage::from_pixel(width, height, BODY_COLOUR);
+
+        let x0 = (width - keys_w) / 2;
+        for key in 0..self.kind.keys() as u32 {
+            let (col, row) = (key % cols, key / cols);
+            let (x, y) = (x0 + col * (kw + gap), gap + row * (kh + gap));
+
+            match self.key(key as u8) {
+                Some(k) => imageops::replace(&mut image, k, x as i64, y as i64),
+                None => {
+                    let black = RgbImage::new(kw, kh);
+                    imageops::replace(&mut image, &black, x as i64, y as i64);
+                }
+            }
+        }
+
+        if let Some(lcd) = &self.lcd {
+            let x = (width - lcd_w) / 2;
+            imageops::replace(&mut image, lcd, x as i64, (2 * gap + keys_h) as i64);
+        }
+
+        image
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn native_round_trip() {
+        for kind in [Kind::Original, Kind::Mini, Kind::Xl] {
+            let (w, h) = kind.image_size();
+            let mut image = RgbImage::new(w as u32, h as u32);
+            image.put_pixel(1, 2, Rgb([255, 0, 0]));
+
+            // Apply the device transform as the driver does when writing keys
+            let native = apply_transform(DynamicImage::ImageRgb8(image.clone()), kind.image_rotation(), kind.image_mirror());
+            let mut native = native.into_rgb8().into_vec();
+            if matches!(kind.image_colour_order(), ColourOrder::BGR) {
+                rgb_to_bgr(&mut native);
+            }
+
+            let mut fb = Framebuffer::new(kind);
+            fb.set_key_native(3, native);
+            assert_eq!(fb.key(3), Some(&image), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn snapshot_layout() {
+        let mut fb = Framebuffer::new(Kind::Plus);
+        fb.set_key(5, RgbImage::from_pixel(120, 120, Rgb([0, 255, 0])));
+
+        let snapshot = fb.snapshot();
+        let gap = Kind::Plus.key_gap() as u32;
+        let x0 = (800 + 2 * gap - (4 * 120 + 3 * gap)) / 2;
+
+        assert_eq!(snapshot.dimensions(), (800 + 2 * gap, 2 * 120 + 100 + 4 * gap));
+        assert_eq!(snapshot.get_pixel(x0 + 120 + gap, 2 * gap + 120).0, [0, 255, 0]);
+        assert_eq!(snapshot.get_pixel(0, 0).0, BODY_COLOUR.0);
+    }
+}
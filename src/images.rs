@@ -1,16 +1,20 @@
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
 use std::str::FromStr;
 
 use image::io::Reader;
-use image::codecs::jpeg::JpegEncoder;
-use image::DynamicImage;
-use image::{imageops::FilterType, ColorType, Pixel, Rgba};
+use image::{DynamicImage, Rgb, RgbImage};
+use image::{imageops::FilterType, Pixel};
 use tracing::error;
 
 use crate::info::{ColourOrder, Mirroring, Rotation};
 use crate::{Error, rgb_to_bgr};
 
 /// Simple Colour object for re-writing backgrounds etc.
-#[derive(Debug, Clone)]
+///
+/// Colours may be parsed from hex (`RRGGBB`, `#RGB`, `#RRGGBB`, `#RRGGBBAA`),
+/// CSS colour names (`steelblue`) or HSL (`hsl(210, 50%, 40%)`, `hsla(210, 50%, 40%, 0.5)`).
+#[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Colour {
@@ -22,44 +26,340 @@ pub struct Colour {
 
     #[cfg_attr(feature = "structopt", structopt(long))]
     pub b: u8,
+
+    #[cfg_attr(feature = "structopt", structopt(long, default_value = "255"))]
+    #[cfg_attr(feature = "serde", serde(default = "opaque"))]
+    /// Alpha (0 is transparent, 255 is opaque)
+    pub a: u8,
+}
+
+#[cfg(feature = "serde")]
+fn opaque() -> u8 {
+    255
+}
+
+impl Colour {
+    /// Create an opaque colour
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    /// Create a colour with alpha
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Blend this colour over an RGB pixel value
+    pub fn blend_over(&self, base: [u8; 3]) -> [u8; 3] {
+        let a = self.a as u16;
+        let mix = |c: u8, b: u8| ((c as u16 * a + b as u16 * (255 - a) + 127) / 255) as u8;
+        [mix(self.r, base[0]), mix(self.g, base[1]), mix(self.b, base[2])]
+    }
+
+    /// Opaque RGB value of this colour over black
+    pub fn to_rgb(&self) -> [u8; 3] {
+        self.blend_over([0, 0, 0])
+    }
+
+    /// Parse an `hsl(h, s%, l%)` or `hsla(h, s%, l%, a)` colour
+    fn from_hsl(s: &str) -> Result<Self, String> {
+        let args = s
+            .strip_prefix("hsla(")
+            .or_else(|| s.strip_prefix("hsl("))
+            .and_then(|a| a.strip_suffix(')'))
+            .ok_or_else(|| format!("Expected colour in the form hsl(h, s%, l%): {}", s))?;
+
+        let args: Vec<&str> = args
+            .split([',', ' ', '/'])
+            .filter(|a| !a.is_empty())
+            .collect();
+        if args.len() != 3 && args.len() != 4 {
+            return Err("Expected 3 or 4 hsl components".to_string());
+        }
+
+        let num = |v: &str| -> Result<f32, String> {
+            v.trim_end_matches("deg")
+                .trim_end_matches('%')
+                .parse::<f32>()
+                .map_err(|e| format!("float parsing error: {}", e))
+        };
+
+        let h = num(args[0])?.rem_euclid(360.0);
+        let sat = (num(args[1])? / 100.0).clamp(0.0, 1.0);
+        let l = (num(args[2])? / 100.0).clamp(0.0, 1.0);
+        let a = match args.get(3) {
+            Some(v) if v.ends_with('%') => num(v)? / 100.0,
+            Some(v) => num(v)?,
+            None => 1.0,
+        };
+
+        // See https://www.w3.org/TR/css-color-3/#hsl-color
+        let k = |n: f32| (n + h / 30.0) % 12.0;
+        let q = sat * l.min(1.0 - l);
+        let f = |n: f32| {
+            let v = l - q * (k(n) - 3.0).min(9.0 - k(n)).clamp(-1.0, 1.0);
+            (v * 255.0).round() as u8
+        };
+
+        Ok(Self::rgba(f(0.0), f(8.0), f(4.0), (a.clamp(0.0, 1.0) * 255.0).round() as u8))
+    }
+
+    /// Parse hex digits in the forms RGB, RGBA, RRGGBB or RRGGBBAA
+    fn from_hex(s: &str) -> Result<Self, String> {
+        if !s.is_ascii() {
+            return Err("Expected colour in the hex form: RRGGBB".to_string());
+        }
+
+        let digit = |i: usize, n: usize| {
+            u8::from_str_radix(&s[i..i + n], 16).map_err(|e| format!("int parsing error: {}", e))
+        };
+
+        match s.len() {
+            3 | 4 => {
+                let c = |i| digit(i, 1).map(|v| v * 17);
+                let a = if s.len() == 4 { c(3)? } else { 255 };
+                Ok(Self::rgba(c(0)?, c(1)?, c(2)?, a))
+            }
+            6 | 8 => {
+                let a = if s.len() == 8 { digit(6, 2)? } else { 255 };
+                Ok(Self::rgba(digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, a))
+            }
+            _ => Err("Expected colour in the hex form: RRGGBB".to_string()),
+        }
+    }
 }
 
 impl FromStr for Colour {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 6 && s.len() != 8 {
-            return Err(format!("Expected colour in the hex form: RRGGBB"));
+        let s = s.trim();
+
+        if let Some(hex) = s.strip_prefix('#') {
+            return Self::from_hex(hex);
+        }
+
+        let lower = s.to_ascii_lowercase();
+        if lower.starts_with("hsl") {
+            return Self::from_hsl(&lower);
+        }
+
+        if let Some(v) = named_colour(&lower) {
+            return Ok(v);
+        }
+
+        // Bare hex for compatibility with earlier releases
+        if (s.len() == 6 || s.len() == 8) && s.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Self::from_hex(s);
         }
 
-        let r =
-            u8::from_str_radix(&s[0..2], 16).map_err(|e| format!("int parsing error: {}", e))?;
-        let g =
-            u8::from_str_radix(&s[2..4], 16).map_err(|e| format!("int parsing error: {}", e))?;
-        let b =
-            u8::from_str_radix(&s[4..6], 16).map_err(|e| format!("int parsing error: {}", e))?;
+        Err(format!("Unrecognised colour: {}", s))
+    }
+}
 
-        Ok(Self { r, g, b })
+/// Look up a CSS named colour
+fn named_colour(name: &str) -> Option<Colour> {
+    if name == "transparent" {
+        return Some(Colour::rgba(0, 0, 0, 0));
     }
+
+    NAMED_COLOURS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| Colour::rgb((v >> 16) as u8, (v >> 8) as u8, *v as u8))
 }
 
-/// Options for image loading and editing
-#[derive(Debug)]
+/// CSS named colours, see https://www.w3.org/TR/css-color-4/#named-colors
+const NAMED_COLOURS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff), ("antiquewhite", 0xfaebd7), ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4), ("azure", 0xf0ffff), ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4), ("black", 0x000000), ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff), ("blueviolet", 0x8a2be2), ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887), ("cadetblue", 0x5f9ea0), ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e), ("coral", 0xff7f50), ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc), ("crimson", 0xdc143c), ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b), ("darkcyan", 0x008b8b), ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9), ("darkgreen", 0x006400), ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b), ("darkmagenta", 0x8b008b), ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00), ("darkorchid", 0x9932cc), ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a), ("darkseagreen", 0x8fbc8f), ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f), ("darkslategrey", 0x2f4f4f), ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3), ("deeppink", 0xff1493), ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969), ("dimgrey", 0x696969), ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222), ("floralwhite", 0xfffaf0), ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff), ("gainsboro", 0xdcdcdc), ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700), ("goldenrod", 0xdaa520), ("gray", 0x808080),
+    ("green", 0x008000), ("greenyellow", 0xadff2f), ("grey", 0x808080),
+    ("honeydew", 0xf0fff0), ("hotpink", 0xff69b4), ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082), ("ivory", 0xfffff0), ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa), ("lavenderblush", 0xfff0f5), ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd), ("lightblue", 0xadd8e6), ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff), ("lightgoldenrodyellow", 0xfafad2), ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90), ("lightgrey", 0xd3d3d3), ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a), ("lightseagreen", 0x20b2aa), ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899), ("lightslategrey", 0x778899), ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0), ("lime", 0x00ff00), ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6), ("magenta", 0xff00ff), ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa), ("mediumblue", 0x0000cd), ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db), ("mediumseagreen", 0x3cb371), ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a), ("mediumturquoise", 0x48d1cc), ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970), ("mintcream", 0xf5fffa), ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5), ("navajowhite", 0xffdead), ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6), ("olive", 0x808000), ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500), ("orangered", 0xff4500), ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa), ("palegreen", 0x98fb98), ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093), ("papayawhip", 0xffefd5), ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f), ("pink", 0xffc0cb), ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6), ("purple", 0x800080), ("rebeccapurple", 0x663399),
+    ("red", 0xff0000), ("rosybrown", 0xbc8f8f), ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513), ("salmon", 0xfa8072), ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57), ("seashell", 0xfff5ee), ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0), ("skyblue", 0x87ceeb), ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f), ("steelblue", 0x4682b4), ("tan", 0xd2b48c),
+    ("teal", 0x008080), ("thistle", 0xd8bfd8), ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0), ("violet", 0xee82ee), ("wheat", 0xf5deb3),
+    ("white", 0xffffff), ("whitesmoke", 0xf5f5f5), ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// How images are fitted to the key
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Fit {
+    /// Scale to fit within the key preserving aspect ratio, filling any remaining area with the background
+    Contain,
+    /// Scale to cover the key preserving aspect ratio, cropping the overflow
+    Cover,
+    /// Scale to the key size ignoring aspect ratio
+    Stretch,
+}
+
+impl FromStr for Fit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "contain" => Ok(Fit::Contain),
+            "cover" | "crop" => Ok(Fit::Cover),
+            "stretch" => Ok(Fit::Stretch),
+            _ => Err(format!("Unrecognised fit mode: {} (expected contain, cover or stretch)", s)),
+        }
+    }
+}
+
+/// Filter used when resizing images
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(f: ResizeFilter) -> Self {
+        match f {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(ResizeFilter::Nearest),
+            "triangle" | "linear" => Ok(ResizeFilter::Triangle),
+            "catmullrom" | "cubic" => Ok(ResizeFilter::CatmullRom),
+            "gaussian" => Ok(ResizeFilter::Gaussian),
+            "lanczos3" | "lanczos" => Ok(ResizeFilter::Lanczos3),
+            _ => Err(format!("Unrecognised resize filter: {}", s)),
+        }
+    }
+}
+
+/// Options for loading and preparing images
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ImageOptions {
     #[cfg_attr(feature = "structopt", structopt(long = "bg"))]
-    /// Background colour
+    /// Background colour, shown behind transparent areas, padding and letterboxing
     background: Option<Colour>,
 
     #[cfg_attr(feature = "structopt", structopt(long))]
     /// Invert colours
     invert: bool,
+
+    #[cfg_attr(feature = "structopt", structopt(long, default_value = "contain"))]
+    /// Fit mode (contain, cover or stretch)
+    fit: Fit,
+
+    #[cfg_attr(feature = "structopt", structopt(long, default_value = "0"))]
+    /// Padding around the image in pixels
+    padding: u32,
+
+    #[cfg_attr(feature = "structopt", structopt(long, default_value = "gaussian"))]
+    /// Resize filter (nearest, triangle, catmullrom, gaussian or lanczos3)
+    filter: ResizeFilter,
+
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    /// Convert the image to grayscale
+    grayscale: bool,
+
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    /// Tint colour, with alpha setting the tint strength
+    tint: Option<Colour>,
 }
 
 impl ImageOptions {
     pub fn new(background: Option<Colour>, invert: bool) -> Self {
-        ImageOptions { background, invert }
+        ImageOptions {
+            background,
+            invert,
+            ..Default::default()
+        }
+    }
+
+    /// Set how the image is fitted to the key
+    pub fn with_fit(mut self, fit: Fit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Set the padding around the image in pixels
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Set the filter used when resizing
+    pub fn with_filter(mut self, filter: ResizeFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Convert the image to grayscale
+    pub fn with_grayscale(mut self, grayscale: bool) -> Self {
+        self.grayscale = grayscale;
+        self
+    }
+
+    /// Tint the image towards a colour
+    pub fn with_tint(mut self, tint: Colour) -> Self {
+        self.tint = Some(tint);
+        self
     }
 }
 
@@ -68,10 +368,74 @@ impl Default for ImageOptions {
         Self {
             background: None,
             invert: false,
+            fit: Fit::Contain,
+            padding: 0,
+            filter: ResizeFilter::Gaussian,
+            grayscale: false,
+            tint: None,
         }
     }
 }
 
+/// Opaque RGB pixel for a colour (over black)
+pub(crate) fn rgb(c: &Colour) -> Rgb<u8> {
+    Rgb(c.to_rgb())
+}
+
+/// Blend an RGB value with the provided alpha over an image pixel
+pub(crate) fn blend(p: &mut Rgb<u8>, c: [u8; 3], alpha: u8) {
+    p.0 = Colour::rgba(c[0], c[1], c[2], alpha).blend_over(p.0);
+}
+
+/// Fit an image to `x` by `y` pixels and apply the provided options
+///
+/// Grayscale, tint and invert apply to the image content only, which is then
+/// composited over the background colour (or black).
+pub(crate) fn prepare_image(image: DynamicImage, x: u32, y: u32, opts: &ImageOptions) -> RgbImage {
+    let (w, h) = (x.saturating_sub(2 * opts.padding), y.saturating_sub(2 * opts.padding));
+
+    let background = opts.background.as_ref().map(|c| c.to_rgb()).unwrap_or([0, 0, 0]);
+    let mut canvas = RgbImage::from_pixel(x, y, Rgb(background));
+    if w == 0 || h == 0 {
+        return canvas;
+    }
+
+    let filter = opts.filter.into();
+    let image = match opts.fit {
+        Fit::Contain => image.resize(w, h, filter),
+        Fit::Cover => image.resize_to_fill(w, h, filter),
+        Fit::Stretch => image.resize_exact(w, h, filter),
+    };
+    let mut image = if opts.grayscale {
+        DynamicImage::ImageLumaA8(image.to_luma_alpha8()).to_rgba8()
+    } else {
+        image.to_rgba8()
+    };
+
+    for p in image.pixels_mut() {
+        if let Some(t) = &opts.tint {
+            let a = t.a as u16;
+            for (v, t) in p.0.iter_mut().zip([t.r, t.g, t.b]) {
+                let tinted = *v as u16 * t as u16 / 255;
+                *v = ((tinted * a + *v as u16 * (255 - a)) / 255) as u8;
+            }
+        }
+        if opts.invert {
+            p.invert();
+        }
+    }
+
+    // Centre the image, compositing over the background
+    let x0 = opts.padding + (w - image.width()) / 2;
+    let y0 = opts.padding + (h - image.height()) / 2;
+    for (ix, iy, p) in image.enumerate_pixels() {
+        let [r, g, b, a] = p.0;
+        blend(canvas.get_pixel_mut(x0 + ix, y0 + iy), [r, g, b], a);
+    }
+
+    canvas
+}
+
 pub(crate) fn apply_transform(
     image: DynamicImage,
     rotation: Rotation,
@@ -94,7 +458,7 @@ pub(crate) fn apply_transform(
 
 /// Load an image from a file, resize to defined x and y, and apply the provided options
 pub(crate) fn load_image(
-    path: &str,
+    path: &Path,
     x: usize,
     y: usize,
     rotate: Rotation,
@@ -102,46 +466,101 @@ pub(crate) fn load_image(
     opts: &ImageOptions,
     colour_order: ColourOrder,
 ) -> Result<Vec<u8>, Error> {
+    // Rasterise vector images at the padded target size so they are not resampled
+    #[cfg(feature = "svg")]
+    if is_svg(path) {
+        let image = load_svg(path, x, y, opts)?;
+        return to_device_pixels(image, x, y, rotate, mirror, opts, colour_order);
+    }
+
     // Open image reader
     let reader = match Reader::open(path) {
         Ok(v) => v,
         Err(e) => {
-            error!("error loading file '{}': {:?}", path, e);
+            error!("error loading file '{}': {:?}", path.display(), e);
             return Err(Error::Io(e));
         }
     };
+    let image = reader.decode().map_err(Error::Image)?;
 
-    // Load image
-    let mut image = reader.decode().map_err(Error::Image)?;
+    to_device_pixels(image, x, y, rotate, mirror, opts, colour_order)
+}
 
-    // Apply background filter / replace
-    // This must be done before transparency is removed
-    if let Some(c) = &opts.background {
-        let rgba = image.as_mut_rgba8().unwrap();
+/// Decode an encoded image from memory
+///
+/// The format is detected from the data, SVG images are rasterised to fit within
+/// `x` by `y` pixels (without the `svg` feature they return [Error::UnsupportedImageMode]).
+pub fn decode_image(data: &[u8], x: usize, y: usize) -> Result<DynamicImage, Error> {
+    decode_image_with(data, x, y, &ImageOptions::default())
+}
 
-        let mut r = Rgba([c.r, c.g, c.b, 0]);
-        if opts.invert {
-            r.invert();
+/// Decode an encoded image from memory for an `x` by `y` image with the provided options
+///
+/// SVG images are rasterised at the padded size with the requested [Fit], so
+/// [prepare_image] does not resample them.
+pub(crate) fn decode_image_with(data: &[u8], x: usize, y: usize, opts: &ImageOptions) -> Result<DynamicImage, Error> {
+    if is_svg_data(data) {
+        #[cfg(feature = "svg")]
+        {
+            let (w, h) = padded_size(x, y, opts);
+            return render_svg(data, w, h, opts.fit);
         }
-
-        for p in rgba.pixels_mut() {
-            r.0[3] = 255 - p.0[3];
-
-            p.blend(&r);
+        #[cfg(not(feature = "svg"))]
+        {
+            let _ = (x, y, opts);
+            return Err(Error::UnsupportedImageMode);
         }
     }
 
-    // Resize image
-    let mut image = image.resize(x as u32, y as u32, FilterType::Gaussian);
+    let image = image::load_from_memory(data)?;
+    Ok(image)
+}
 
-    // Apply the requested mirroring transformation
-    image = apply_transform(image, rotate, mirror);
+/// Decode an encoded image from a reader
+///
+/// See [decode_image] for format detection.
+pub fn decode_reader<R: Read + Seek>(reader: R, x: usize, y: usize) -> Result<DynamicImage, Error> {
+    decode_reader_with(reader, x, y, &ImageOptions::default())
+}
+
+/// Decode an encoded image from a reader, see [decode_image_with]
+pub(crate) fn decode_reader_with<R: Read + Seek>(reader: R, x: usize, y: usize, opts: &ImageOptions) -> Result<DynamicImage, Error> {
+    let reader = Reader::new(BufReader::new(reader)).with_guessed_format()?;
 
-    // Invert image if requir
-    if opts.invert {
-        image.invert();
+    // SVG is not detected by the image crate, so fall back to sniffing the data
+    if reader.format().is_none() {
+        let mut data = vec![];
+        reader.into_inner().read_to_end(&mut data)?;
+        return decode_image_with(&data, x, y, opts);
     }
 
+    let image = reader.decode()?;
+    Ok(image)
+}
+
+/// Image area within the padding for an `x` by `y` image
+#[cfg(feature = "svg")]
+fn padded_size(x: usize, y: usize, opts: &ImageOptions) -> (usize, usize) {
+    let pad = 2 * opts.padding as usize;
+    (x.saturating_sub(pad).max(1), y.saturating_sub(pad).max(1))
+}
+
+/// Fit a decoded image to `x` by `y` pixels, apply the provided options
+/// and transform, and convert to packed pixels in the device colour order
+pub(crate) fn to_device_pixels(
+    image: DynamicImage,
+    x: usize,
+    y: usize,
+    rotate: Rotation,
+    mirror: Mirroring,
+    opts: &ImageOptions,
+    colour_order: ColourOrder,
+) -> Result<Vec<u8>, Error> {
+    let image = prepare_image(image, x as u32, y as u32, opts);
+
+    // Apply the requested mirroring transformation
+    let image = apply_transform(DynamicImage::ImageRgb8(image), rotate, mirror);
+
     // Convert to vector with correct encoding
     let mut v= image.to_rgb8().into_vec();
     if matches!(colour_order, ColourOrder::BGR) {
@@ -156,12 +575,96 @@ pub(crate) fn load_image(
     Ok(v)
 }
 
-/// Encodes a BGR bitmap into a JPEG image for outputting to a V2 device
-pub(crate) fn encode_jpeg(image: &[u8], width: usize, height: usize) -> Result<Vec<u8>, Error> {
-    let mut buf = Vec::new();
-    let mut encoder = JpegEncoder::new_with_quality(&mut buf, 100);
-    encoder.encode(image, width as u32, height as u32, ColorType::Rgb8)?;
-    Ok(buf)
+/// Check whether in-memory data looks like an SVG document
+///
+/// The XML declaration, processing instructions, comments, DOCTYPE and
+/// whitespace are skipped before looking for the `<svg` root element.
+fn is_svg_data(data: &[u8]) -> bool {
+    let mut rest = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+
+    loop {
+        let start = rest.iter().position(|c| !c.is_ascii_whitespace()).unwrap_or(rest.len());
+        rest = &rest[start..];
+
+        let skipped = if rest.starts_with(b"<?") {
+            skip_past(rest, b"?>")
+        } else if rest.starts_with(b"<!--") {
+            skip_past(rest, b"-->")
+        } else if rest.len() >= 9 && rest[..9].eq_ignore_ascii_case(b"<!DOCTYPE") {
+            // The internal subset may contain '>', so skip it first
+            match rest.iter().position(|c| *c == b'[' || *c == b'>') {
+                Some(i) if rest[i] == b'[' => skip_past(&rest[i..], b"]").and_then(|r| skip_past(r, b">")),
+                Some(i) => Some(&rest[i + 1..]),
+                None => None,
+            }
+        } else {
+            return rest.starts_with(b"<svg");
+        };
+
+        rest = match skipped {
+            Some(r) => r,
+            None => return false,
+        };
+    }
+}
+
+/// Skip past the first occurrence of `pattern`
+fn skip_past<'a>(data: &'a [u8], pattern: &[u8]) -> Option<&'a [u8]> {
+    data.windows(pattern.len())
+        .position(|w| w == pattern)
+        .map(|i| &data[i + pattern.len()..])
+}
+
+/// Check whether a path refers to an SVG image
+#[cfg(feature = "svg")]
+fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.eq_ignore_ascii_case("svg"))
+        .unwrap_or(false)
+}
+
+#[cfg(feature = "svg")]
+fn load_svg(path: &Path, x: usize, y: usize, opts: &ImageOptions) -> Result<DynamicImage, Error> {
+    let data = std::fs::read(path).map_err(|e| {
+        error!("error loading file '{}': {:?}", path.display(), e);
+        Error::Io(e)
+    })?;
+    let (w, h) = padded_size(x, y, opts);
+    render_svg(&data, w, h, opts.fit)
+}
+
+/// Rasterise an SVG image to `x` by `y` pixels
+///
+/// The image is scaled as described by [Fit] and centred. With [Fit::Contain]
+/// any remaining area is left transparent, with [Fit::Cover] the overflow is cropped.
+#[cfg(feature = "svg")]
+pub fn render_svg(data: &[u8], x: usize, y: usize, fit: Fit) -> Result<DynamicImage, Error> {
+    use image::{Rgba, RgbaImage};
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default())?;
+    let size = tree.size();
+
+    let (sx, sy) = (x as f32 / size.width(), y as f32 / size.height());
+    let (sx, sy) = match fit {
+        Fit::Contain => (sx.min(sy), sx.min(sy)),
+        Fit::Cover => (sx.max(sy), sx.max(sy)),
+        Fit::Stretch => (sx, sy),
+    };
+    let dx = (x as f32 - size.width() * sx) / 2.0;
+    let dy = (y as f32 - size.height() * sy) / 2.0;
+
+    let mut pixmap = tiny_skia::Pixmap::new(x as u32, y as u32).ok_or(Error::InvalidImageSize)?;
+    let transform = tiny_skia::Transform::from_row(sx, 0.0, 0.0, sy, dx, dy);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    let mut image = RgbaImage::new(x as u32, y as u32);
+    for (p, c) in image.pixels_mut().zip(pixmap.pixels()) {
+        let c = c.demultiply();
+        *p = Rgba([c.red(), c.green(), c.blue(), c.alpha()]);
+    }
+
+    Ok(DynamicImage::ImageRgba8(image))
 }
 
 #[cfg(test)]
@@ -171,7 +674,7 @@ mod test {
     #[test]
     fn load_images() {
         let _image = load_image(
-            "./icons/power.png",
+            Path::new("./icons/power.png"),
             72,
             72,
             Rotation::Rot180,
@@ -181,4 +684,122 @@ mod test {
         )
         .expect("error loading image");
     }
+
+    #[test]
+    fn decode_sources() {
+        let data = std::fs::read("./icons/power.png").unwrap();
+
+        let a = decode_image(&data, 72, 72).unwrap();
+        let b = decode_reader(std::io::Cursor::new(&data), 72, 72).unwrap();
+        assert_eq!(a.to_rgba8(), b.to_rgba8());
+
+        assert!(decode_image(b"not an image", 72, 72).is_err());
+    }
+
+    #[test]
+    fn detect_svg_data() {
+        assert!(is_svg_data(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"));
+        assert!(is_svg_data(b"<?xml version=\"1.0\"?>\n<svg/>"));
+        assert!(is_svg_data(b"<!-- Generator: Adobe Illustrator -->\n<svg/>"));
+        assert!(is_svg_data(b"<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\" \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\">\n<svg/>"));
+
+        assert!(!is_svg_data(b"<!-- <svg -->"));
+        assert!(!is_svg_data(b"<html><svg/></html>"));
+        assert!(!is_svg_data(&std::fs::read("./icons/power.png").unwrap()));
+    }
+
+    #[test]
+    fn fit_images() {
+        // Wide RGB image with no alpha channel
+        let wide = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, Rgb([255, 0, 0])));
+        let bg = Colour::rgb(0, 0, 255);
+
+        let opts = ImageOptions::new(Some(bg.clone()), false);
+        let image = prepare_image(wide.clone(), 10, 10, &opts);
+        assert_eq!(image.get_pixel(5, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(5, 5).0, [255, 0, 0]);
+
+        let image = prepare_image(wide.clone(), 10, 10, &opts.clone().with_fit(Fit::Cover));
+        assert_eq!(image.get_pixel(5, 0).0, [255, 0, 0]);
+
+        let image = prepare_image(wide, 10, 10, &opts.with_fit(Fit::Stretch).with_padding(2).with_grayscale(true));
+        assert_eq!(image.get_pixel(1, 5).0, [0, 0, 255]);
+        let [r, g, b] = image.get_pixel(5, 5).0;
+        assert!(r == g && g == b && r > 0);
+    }
+
+    #[test]
+    fn parse_colours() {
+        let c = |s: &str| Colour::from_str(s).unwrap();
+
+        assert_eq!(c("FF8000"), Colour::rgb(255, 128, 0));
+        assert_eq!(c("FF800080"), Colour::rgba(255, 128, 0, 128));
+        assert_eq!(c("#f80"), Colour::rgb(255, 136, 0));
+        assert_eq!(c("#FF8000"), Colour::rgb(255, 128, 0));
+        assert_eq!(c("#ff800040"), Colour::rgba(255, 128, 0, 64));
+        assert_eq!(c("SteelBlue"), Colour::rgb(70, 130, 180));
+        assert_eq!(c("transparent").a, 0);
+        assert_eq!(c("hsl(120, 100%, 25%)"), Colour::rgb(0, 128, 0));
+        assert_eq!(c("hsla(0, 100%, 50%, 0.5)"), Colour::rgba(255, 0, 0, 128));
+
+        assert!(Colour::from_str("#12345").is_err());
+        assert!(Colour::from_str("notacolour").is_err());
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn render_svgs() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="20">
+            <rect width="10" height="20" fill="#ff0000"/></svg>"##;
+
+        let image = render_svg(svg, 96, 96, Fit::Contain).expect("error rendering svg").to_rgba8();
+        assert_eq!(image.dimensions(), (96, 96));
+
+        // Scaled to fit vertically and centred horizontally
+        assert_eq!(image.get_pixel(48, 48).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 48).0[3], 0);
+
+        // SVG data is detected when decoding from memory or readers
+        let decoded = decode_reader(std::io::Cursor::new(&svg[..]), 96, 96).unwrap();
+        assert_eq!(decoded.to_rgba8(), image);
+
+        // Including behind a long prolog with comments and a DOCTYPE
+        let prolog = format!(
+            "\u{feff}<?xml version=\"1.0\"?>\n<!-- Generator: {} -->\n<!DOCTYPE svg PUBLIC \"-//W3C//DTD SVG 1.1//EN\" \"http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd\" [ <!ENTITY a \"x\"> ]>\n",
+            "x".repeat(300)
+        );
+        let decoded = decode_image(&[prolog.as_bytes(), &svg[..]].concat(), 96, 96).unwrap();
+        assert_eq!(decoded.to_rgba8(), image);
+
+        // Padded SVG files are rasterised at the inner size, keeping edges crisp
+        let path = std::env::temp_dir().join("streamdeck-padded-test.svg");
+        std::fs::write(&path, &svg[..]).unwrap();
+        let opts = ImageOptions::default().with_fit(Fit::Stretch).with_padding(10);
+        let data = load_image(&path, 72, 72, Rotation::Rot0, Mirroring::None, &opts, ColourOrder::RGB).unwrap();
+        let image = RgbImage::from_raw(72, 72, data).unwrap();
+        assert_eq!(image.get_pixel(36, 10).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(36, 61).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(36, 9).0, [0, 0, 0]);
+
+        // Cover and stretch fill the padded area to the edges
+        for fit in [Fit::Cover, Fit::Stretch] {
+            let opts = ImageOptions::default().with_fit(fit).with_padding(10);
+            let data = load_image(&path, 72, 72, Rotation::Rot0, Mirroring::None, &opts, ColourOrder::RGB).unwrap();
+            let image = RgbImage::from_raw(72, 72, data).unwrap();
+            for (x, y) in [(10, 10), (61, 10), (10, 61), (61, 61)] {
+                assert_eq!(image.get_pixel(x, y).0, [255, 0, 0], "{:?} at {},{}", fit, x, y);
+            }
+            assert_eq!(image.get_pixel(9, 36).0, [0, 0, 0]);
+
+            let image = render_svg(svg, 96, 48, fit).unwrap().to_rgba8();
+            assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255], "{:?}", fit);
+            assert_eq!(image.get_pixel(95, 47).0, [255, 0, 0, 255], "{:?}", fit);
+        }
+
+        // SVG data is rasterised at the padded size too
+        let data = std::fs::read(&path).unwrap();
+        let opts = ImageOptions::default().with_fit(Fit::Stretch).with_padding(10);
+        let image = decode_image_with(&data, 72, 72, &opts).unwrap();
+        assert_eq!((image.width(), image.height()), (52, 52));
+    }
 }
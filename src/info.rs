@@ -1,5 +1,5 @@
 /// Stream Deck Device Kinds
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Kind {
     Original,
     OriginalV2,
@@ -84,6 +84,18 @@ impl Kind {
         }
     }
 
+    /// Approximate gap between keys, in key image pixels
+    ///
+    /// This is used to lay out images spanning multiple keys as they physically appear.
+    pub fn key_gap(&self) -> usize {
+        match self {
+            Kind::Original | Kind::OriginalV2 | Kind::Mk2 => 18,
+            Kind::Mini => 20,
+            Kind::Xl => 24,
+            Kind::Plus => 40,
+        }
+    }
+
     pub fn knobs(&self) -> u8 {
         match self {
             Kind::Plus => 4,
@@ -169,6 +181,12 @@ impl Kind {
         }
     }
 
+    /// Size of the LCD area above each knob
+    pub fn lcd_segment_size(&self) -> Option<(usize, usize)> {
+        let (w, h) = self.lcd_size()?;
+        Some((w / self.knobs() as usize, h))
+    }
+
     pub(crate) fn is_v2(&self) -> bool {
         match self {
             Kind::OriginalV2 | Kind::Xl | Kind::Mk2 | Kind::Plus => true,
@@ -0,0 +1,345 @@
+//! Partial updates for the LCD strip (Stream Deck Plus)
+//!
+//! An [LcdSurface] holds a host side copy of the LCD and tracks the regions
+//! changed since the last commit. On commit the regions are trimmed to the
+//! pixels that actually changed, merged into a small number of rectangles,
+//! and only those are encoded and sent.
+
+use image::{imageops, Rgb, RgbImage};
+use tracing::trace;
+
+use crate::framebuffer::Framebuffer;
+use crate::images::Colour;
+use crate::info::Kind;
+use crate::{Error, StreamDeck};
+
+/// Rectangular region of the LCD
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    pub fn area(&self) -> u32 {
+        self.w * self.h
+    }
+
+    /// Smallest region containing both regions
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x0, y0) = (self.x.min(other.x), self.y.min(other.y));
+        let x1 = (self.x + self.w).max(other.x + other.w);
+        let y1 = (self.y + self.h).max(other.y + other.h);
+        Rect::new(x0, y0, x1 - x0, y1 - y0)
+    }
+
+    /// Check whether regions overlap or share an edge
+    pub fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.x + other.w
+            && other.x <= self.x + self.w
+            && self.y <= other.y + other.h
+            && other.y <= self.y + self.h
+    }
+
+    /// Clip the region to a `width` by `height` area
+    fn clip(&self, width: u32, height: u32) -> Option<Rect> {
+        let x1 = (self.x + self.w).min(width);
+        let y1 = (self.y + self.h).min(height);
+        if self.x >= x1 || self.y >= y1 {
+            return None;
+        }
+        Some(Rect::new(self.x, self.y, x1 - self.x, y1 - self.y))
+    }
+}
+
+/// Host side LCD surface with dirty region tracking
+pub struct LcdSurface {
+    image: RgbImage,
+    committed: RgbImage,
+    // Per pixel flags for committed contents matching the device, row major
+    known: Vec<bool>,
+    dirty: Vec<Rect>,
+    max_regions: usize,
+}
+
+impl LcdSurface {
+    /// Create a surface for the device LCD, initialised from the current LCD contents
+    ///
+    /// Areas with unknown contents (see [Framebuffer::lcd_known]) are sent when drawn,
+    /// even if unchanged.
+    pub fn new(deck: &StreamDeck) -> Result<Self, Error> {
+        Self::from_framebuffer(deck.framebuffer())
+    }
+
+    fn from_framebuffer(framebuffer: &Framebuffer) -> Result<Self, Error> {
+        let image = framebuffer.lcd().ok_or(Error::NoLcd)?.clone();
+
+        Ok(Self {
+            committed: image.clone(),
+            image,
+            known: framebuffer.lcd_known_mask().to_vec(),
+            dirty: vec![],
+            max_regions: 4,
+        })
+    }
+
+    /// Create a blank surface for a device kind
+    pub fn for_kind(kind: Kind) -> Result<Self, Error> {
+        let (w, h) = kind.lcd_size().ok_or(Error::NoLcd)?;
+        let image = RgbImage::new(w as u32, h as u32);
+
+        Ok(Self {
+            committed: image.clone(),
+            image,
+            known: vec![true; w * h],
+            dirty: vec![],
+            max_regions: 4,
+        })
+    }
+
+    /// Set the maximum number of regions sent per commit
+    ///
+    /// Each region is a separate JPEG, so fewer larger regions may be faster than many small ones.
+    pub fn with_max_regions(mut self, max: usize) -> Self {
+        self.max_regions = max.max(1);
+        self
+    }
+
+    /// Fetch the surface contents
+    pub fn image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// Draw an image at `x`, `y`
+    pub fn draw(&mut self, x: u32, y: u32, image: &RgbImage) {
+        imageops::replace(&mut self.image, image, x as i64, y as i64);
+        self.mark_dirty(Rect::new(x, y, image.width(), image.height()));
+    }
+
+    /// Fill a region with a colour
+    pub fn fill(&mut self, region: Rect, colour: &Colour) {
+        let c = Rgb(colour.to_rgb());
+        if let Some(r) = region.clip(self.image.width(), self.image.height()) {
+            for y in r.y..r.y + r.h {
+                for x in r.x..r.x + r.w {
+                    self.image.put_pixel(x, y, c);
+                }
+            }
+            self.mark_dirty(r);
+        }
+    }
+
+    /// Modify a region of the surface in place, marking it dirty
+    pub fn update<F: FnOnce(&mut RgbImage)>(&mut self, region: Rect, f: F) {
+        let r = match region.clip(self.image.width(), self.image.height()) {
+            Some(r) => r,
+            None => return,
+        };
+
+        let mut sub = imageops::crop_imm(&self.image, r.x, r.y, r.w, r.h).to_image();
+        f(&mut sub);
+        self.draw(r.x, r.y, &sub);
+    }
+
+    /// Mark a region as changed
+    pub fn mark_dirty(&mut self, region: Rect) {
+        if let Some(r) = region.clip(self.image.width(), self.image.height()) {
+            self.dirty.push(r);
+        }
+    }
+
+    /// Compute the regions to send and mark the surface as committed
+    pub fn take_regions(&mut self) -> Vec<Rect> {
+        let regions = self.pending_regions();
+        for r in &regions {
+            self.mark_committed(r);
+        }
+
+        regions
+    }
+
+    /// Send changed regions to the device
+    ///
+    /// Returns the regions written. Regions not written due to an error stay
+    /// dirty and are sent on the next commit.
+    pub fn commit(&mut self, deck: &mut StreamDeck) -> Result<Vec<Rect>, Error> {
+        self.commit_with(|r, sub| deck.write_lcd_raw(r.x as u16, r.y as u16, r.w as u16, r.h as u16, sub.as_raw()))
+    }
+
+    fn commit_with<F: FnMut(&Rect, &RgbImage) -> Result<(), Error>>(&mut self, mut write: F) -> Result<Vec<Rect>, Error> {
+        let regions = self.pending_regions();
+
+        for (i, r) in regions.iter().enumerate() {
+            trace!("writing lcd region {:?}", r);
+            let sub = imageops::crop_imm(&self.image, r.x, r.y, r.w, r.h).to_image();
+            if let Err(e) = write(r, &sub) {
+                self.dirty.extend_from_slice(&regions[i..]);
+                return Err(e);
+            }
+            self.mark_committed(r);
+        }
+
+        Ok(regions)
+    }
+
+    /// Drain the dirty list into merged regions of changed pixels
+    fn pending_regions(&mut self) -> Vec<Rect> {
+        let (image, committed, known) = (&self.image, &self.committed, &self.known);
+        let dirty: Vec<_> = self
+            .dirty
+            .drain(..)
+            .filter_map(|r| changed_bounds(image, committed, known, r))
+            .collect();
+
+        merge_regions(dirty, self.max_regions)
+    }
+
+    /// Record a region as displayed on the device
+    fn mark_committed(&mut self, r: &Rect) {
+        let sub = imageops::crop_imm(&self.image, r.x, r.y, r.w, r.h).to_image();
+        imageops::replace(&mut self.committed, &sub, r.x as i64, r.y as i64);
+
+        let width = self.image.width();
+        for y in r.y..r.y + r.h {
+            for x in r.x..r.x + r.w {
+                self.known[(y * width + x) as usize] = true;
+            }
+        }
+    }
+}
+
+/// Trim a region to the bounding box of pixels that differ between images or are unknown
+fn changed_bounds(a: &RgbImage, b: &RgbImage, known: &[bool], r: Rect) -> Option<Rect> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for y in r.y..r.y + r.h {
+        for x in r.x..r.x + r.w {
+            if a.get_pixel(x, y) != b.get_pixel(x, y) || !known[(y * a.width() + x) as usize] {
+                let (x0, y0, x1, y1) = bounds.unwrap_or((x, y, x, y));
+                bounds = Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y)));
+            }
+        }
+    }
+
+    bounds.map(|(x0, y0, x1, y1)| Rect::new(x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+}
+
+/// Merge touching regions, then merge the cheapest pairs until at most `max` remain
+fn merge_regions(mut regions: Vec<Rect>, max: usize) -> Vec<Rect> {
+    // Merge regions that overlap or touch, repeating until stable
+    let mut merged = true;
+    while merged {
+        merged = false;
+        'outer: for i in 0..regions.len() {
+            for j in i + 1..regions.len() {
+                if regions[i].touches(&regions[j]) {
+                    regions[i] = regions[i].union(&regions[j]);
+                    regions.swap_remove(j);
+                    merged = true;
+                    break 'outer;
+                }
+            }
+        }
+    }
+
+    // Merge pairs adding the least area until within the limit
+    while regions.len() > max.max(1) {
+        let mut best = (0, 1, u32::MAX);
+        for i in 0..regions.len() {
+            for j in i + 1..regions.len() {
+                let cost = regions[i].union(&regions[j]).area() - regions[i].area() - regions[j].area();
+                if cost < best.2 {
+                    best = (i, j, cost);
+                }
+            }
+        }
+        let (i, j, _) = best;
+        regions[i] = regions[i].union(&regions[j]);
+        regions.swap_remove(j);
+    }
+
+    regions.sort_by_key(|r| (r.y, r.x));
+    regions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_dirty_regions() {
+        let regions = vec![
+            Rect::new(0, 0, 10, 10),
+            Rect::new(10, 0, 10, 10),
+            Rect::new(100, 0, 10, 10),
+            Rect::new(300, 0, 10, 10),
+        ];
+
+        let merged = merge_regions(regions.clone(), 4);
+        assert_eq!(merged, vec![Rect::new(0, 0, 20, 10), Rect::new(100, 0, 10, 10), Rect::new(300, 0, 10, 10)]);
+
+        let merged = merge_regions(regions, 2);
+        assert_eq!(merged, vec![Rect::new(0, 0, 110, 10), Rect::new(300, 0, 10, 10)]);
+    }
+
+    #[test]
+    fn track_changes() {
+        let mut lcd = LcdSurface::for_kind(Kind::Plus).unwrap();
+
+        // Unchanged pixels are not sent
+        lcd.fill(Rect::new(0, 0, 200, 100), &Colour::rgb(0, 0, 0));
+        assert!(lcd.take_regions().is_empty());
+
+        lcd.fill(Rect::new(0, 0, 200, 100), &Colour::rgb(0, 0, 0));
+        lcd.update(Rect::new(200, 0, 200, 100), |i| i.put_pixel(10, 20, Rgb([255, 0, 0])));
+        assert_eq!(lcd.take_regions(), vec![Rect::new(210, 20, 1, 1)]);
+        assert!(lcd.take_regions().is_empty());
+    }
+
+    #[test]
+    fn send_unknown_areas() {
+        // As after connecting, then writing pre-encoded JPEG data to part of the LCD
+        let mut fb = Framebuffer::new(Kind::Plus);
+        fb.set_lcd_region(0, 0, &RgbImage::new(800, 100));
+        fb.invalidate_lcd_region(100, 0, 200, 100);
+
+        // Black is unchanged in known areas but always sent over unknown ones
+        let mut lcd = LcdSurface::from_framebuffer(&fb).unwrap();
+        lcd.fill(Rect::new(0, 0, 400, 100), &Colour::rgb(0, 0, 0));
+        assert_eq!(lcd.take_regions(), vec![Rect::new(100, 0, 200, 100)]);
+
+        lcd.fill(Rect::new(0, 0, 400, 100), &Colour::rgb(0, 0, 0));
+        assert!(lcd.take_regions().is_empty());
+    }
+
+    #[test]
+    fn retry_failed_commit() {
+        let mut lcd = LcdSurface::for_kind(Kind::Plus).unwrap().with_max_regions(2);
+        lcd.fill(Rect::new(0, 0, 10, 10), &Colour::rgb(255, 0, 0));
+        lcd.fill(Rect::new(400, 0, 10, 10), &Colour::rgb(0, 255, 0));
+
+        // The first region is written before the failure, the second is kept for the next commit
+        let mut written = 0;
+        let res = lcd.commit_with(|_, _| {
+            written += 1;
+            match written {
+                1 => Ok(()),
+                _ => Err(Error::NoData),
+            }
+        });
+        assert!(res.is_err());
+
+        let regions = lcd.commit_with(|_, sub| {
+            assert_eq!(sub.get_pixel(0, 0).0, [0, 255, 0]);
+            Ok(())
+        });
+        assert_eq!(regions.unwrap(), vec![Rect::new(400, 0, 10, 10)]);
+        assert!(lcd.take_regions().is_empty());
+    }
+}
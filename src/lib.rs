@@ -1,35 +1,106 @@
-use std::{io::Error as IoError};
+use std::borrow::Cow;
+use std::io::{Error as IoError, Read, Seek};
+use std::path::Path;
 use std::time::Duration;
 
 extern crate hidapi;
 use hidapi::{HidApi, HidDevice, HidError};
 
 extern crate image;
-use image::{DynamicImage, ImageError};
-#[cfg(feature = "text")]
-use image::{ImageBuffer, Rgb};
+use image::{DynamicImage, ImageError, RgbImage};
 
 use tracing::{trace, debug};
 
 pub mod images;
-use crate::images::{apply_transform, encode_jpeg};
-pub use crate::images::{Colour, ImageOptions};
+use crate::images::apply_transform;
+pub use crate::images::{Colour, Fit, ImageOptions, ResizeFilter};
+
+pub mod encoder;
+use crate::encoder::encode_with;
+pub use crate::encoder::{jpeg_dimensions, prebake_dir, ChromaSubsampling, EncodeOptions, ImageEncoder, ImageJpegEncoder};
 
 pub mod info;
 pub use info::*;
 
+pub mod face;
+pub use face::KeyFace;
+
+pub mod widgets;
+pub use widgets::Widget;
+
+pub mod framebuffer;
+pub use framebuffer::Framebuffer;
+
+pub mod profile;
+pub use profile::{ColourProfile, ColourProfiles};
+
+pub mod lcd;
+pub use lcd::LcdSurface;
+
+pub mod surface;
+pub use surface::Target;
+
+pub mod region;
+pub use region::{Region, RegionWidget, Regions};
+
+pub mod pages;
+pub use pages::{Action, Page, PageStack};
+
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "config")]
+pub use config::Config;
+
+#[cfg(feature = "qr")]
+pub mod qr;
+#[cfg(feature = "qr")]
+pub use qr::Qr;
+
+pub mod transitions;
+pub use transitions::{Direction, Transition};
+
+pub mod animation;
+pub use animation::{Animator, Easing, Tween};
+
 #[cfg(feature = "text")]
-use imageproc::drawing::draw_text_mut;
+pub mod text;
 #[cfg(feature = "text")]
-use rusttype::{Font, Scale};
+pub use text::{HAlign, TextOptions, TextPosition, Typeface, VAlign};
+
+#[cfg(feature = "shaping")]
+pub mod shaping;
+
 #[cfg(feature = "text")]
-use std::str::FromStr;
+pub mod fonts;
+
+#[cfg(feature = "text")]
+pub mod marquee;
+#[cfg(feature = "text")]
+pub use marquee::Marquee;
+
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded;
+#[cfg(feature = "embedded-graphics")]
+pub use embedded::{KeyCanvas, LcdCanvas};
+
+#[cfg(feature = "canvas")]
+pub mod canvas;
+#[cfg(feature = "canvas")]
+pub use canvas::Canvas;
+
 use thiserror::Error;
 
 /// StreamDeck object
 pub struct StreamDeck {
     kind: Kind,
     device: HidDevice,
+    encoder: Box<dyn ImageEncoder>,
+    encode_options: EncodeOptions,
+    framebuffer: Framebuffer,
+    colour_profile: Option<ColourProfile>,
+    // Pre-encoded solid colour frames for clear and fill operations
+    solid_key: Option<([u8; 3], DeviceImage)>,
+    solid_lcd: Option<([u8; 3], Vec<u8>)>,
 }
 
 /// Helper object for filtering device connections
@@ -62,19 +133,50 @@ pub enum Error {
     Io(#[from] IoError),
     #[error(transparent)]
     Image(#[from] ImageError),
+    #[cfg(feature = "svg")]
+    #[error(transparent)]
+    Svg(#[from] resvg::usvg::Error),
+    #[cfg(feature = "jpeg-encoder")]
+    #[error(transparent)]
+    JpegEncoder(#[from] jpeg_encoder::EncodingError),
+    #[cfg(feature = "qr")]
+    #[error(transparent)]
+    Qr(#[from] qrcode::types::QrError),
 
     #[error("invalid image size")]
     InvalidImageSize,
     #[error("invalid key index")]
     InvalidKeyIndex,
+    #[error("invalid font data")]
+    InvalidFont,
+    #[error("invalid colour lut: {0}")]
+    InvalidLut(String),
     #[error("unrecognised pid")]
     UnrecognisedPID,
+    #[error("device has no lcd")]
+    NoLcd,
+    #[error("invalid jpeg data")]
+    InvalidJpeg,
+    #[error("device does not support this image format")]
+    UnsupportedImageMode,
+    #[cfg(feature = "qr")]
+    #[error("qr code does not fit")]
+    QrTooLarge,
+    #[error("region overlaps an existing region")]
+    RegionOverlap,
+    #[error("unknown page: {0}")]
+    UnknownPage(String),
+    #[error("invalid config: {0}")]
+    Config(String),
     #[error("no data")]
     NoData,
 }
 
+#[derive(Clone)]
 pub struct DeviceImage {
     data: Vec<u8>,
+    // Device native pixels (where known) for the framebuffer mirror
+    pixels: Option<Vec<u8>>,
 }
 
 impl DeviceImage {
@@ -87,11 +189,68 @@ impl DeviceImage {
 impl From<Vec<u8>> for DeviceImage {
     fn from(data: Vec<u8>) -> Self {
         Self {
-            data
+            data,
+            pixels: None,
+        }
+    }
+}
+
+/// Image conversion state borrowed from [StreamDeck]
+///
+/// This is split out as the HID device is not `Sync`, so images can be prepared
+/// on other threads.
+struct ImagePipeline<'a> {
+    kind: Kind,
+    encoder: &'a dyn ImageEncoder,
+    encode_options: &'a EncodeOptions,
+    colour_profile: Option<&'a ColourProfile>,
+}
+
+impl<'a> ImagePipeline<'a> {
+    /// Convert device native pixels into the device dependent format
+    fn convert(&self, image: Vec<u8>) -> Result<DeviceImage, Error> {
+        // Check image dimensions
+        if image.len() != self.kind.image_size_bytes() {
+            return Err(Error::InvalidImageSize);
         }
+
+        // Colour correction applies to the device data only, the framebuffer keeps the original
+        let data = match self.colour_profile {
+            Some(profile) => {
+                let mut data = image.clone();
+                profile.apply(&mut data, &self.kind.image_colour_order());
+                Cow::Owned(data)
+            }
+            None => Cow::Borrowed(&image[..]),
+        };
+
+        let data = match self.kind.image_mode() {
+            ImageMode::Bmp => data.into_owned(),
+            ImageMode::Jpeg => {
+                let (w, h) = self.kind.image_size();
+                let payload = self.kind.image_report_len() - self.kind.image_report_header_len();
+                encode_with(self.encoder, &data, w, h, self.encode_options, payload)?
+            }
+        };
+        Ok(DeviceImage{ data, pixels: Some(image) })
+    }
+
+    /// Transform an image for the device and convert it
+    fn convert_dynamic(&self, image: DynamicImage) -> Result<DeviceImage, Error> {
+        let image = apply_transform(image, self.kind.image_rotation(), self.kind.image_mirror());
+        let mut data = image.into_rgb8().into_vec();
+        if matches!(self.kind.image_colour_order(), ColourOrder::BGR) {
+            rgb_to_bgr(&mut data);
+        }
+        self.convert(data)
     }
 }
 
+/// LCD image report sizes (Stream Deck Plus)
+const LCD_MAX_PACKET_SIZE: usize = 1024;
+const LCD_PACKET_HEADER_LENGTH: usize = 16;
+const LCD_MAX_PAYLOAD_SIZE: usize = LCD_MAX_PACKET_SIZE - LCD_PACKET_HEADER_LENGTH;
+
 /// Device USB Product Identifiers (PIDs)
 pub mod pids {
     pub const ORIGINAL: u16 = 0x0060;
@@ -139,7 +298,16 @@ impl StreamDeck {
         }?;
 
         // Return streamdeck object
-        Ok(StreamDeck { device, kind })
+        Ok(StreamDeck {
+            device,
+            kind,
+            encoder: Box::new(ImageJpegEncoder),
+            encode_options: EncodeOptions::default(),
+            framebuffer: Framebuffer::new(kind),
+            colour_profile: None,
+            solid_key: None,
+            solid_lcd: None,
+        })
     }
 
     /// Fetch the connected device kind
@@ -150,6 +318,54 @@ impl StreamDeck {
         self.kind
     }
 
+    /// Replace the JPEG encoder used for V2 devices
+    pub fn set_encoder(&mut self, encoder: impl ImageEncoder + 'static) {
+        self.encoder = Box::new(encoder);
+        self.clear_solid_frames();
+    }
+
+    /// Set the JPEG quality, subsampling and size budget for V2 devices
+    pub fn set_encode_options(&mut self, opts: EncodeOptions) {
+        self.encode_options = opts;
+        self.clear_solid_frames();
+    }
+
+    /// Fetch the current JPEG encoding options
+    pub fn encode_options(&self) -> &EncodeOptions {
+        &self.encode_options
+    }
+
+    /// Set the colour correction applied to images written to the device
+    pub fn set_colour_profile(&mut self, profile: Option<ColourProfile>) {
+        self.colour_profile = profile;
+        self.clear_solid_frames();
+    }
+
+    /// Fetch the current colour correction profile
+    pub fn colour_profile(&self) -> Option<&ColourProfile> {
+        self.colour_profile.as_ref()
+    }
+
+    /// Select the colour profile for this device by serial or kind
+    ///
+    /// Returns whether a matching profile was found.
+    pub fn apply_colour_profiles(&mut self, profiles: &ColourProfiles) -> Result<bool, Error> {
+        let serial = self.serial()?;
+        self.colour_profile = profiles.lookup(self.kind, Some(&serial)).cloned();
+        self.clear_solid_frames();
+        Ok(self.colour_profile.is_some())
+    }
+
+    /// Fetch the host side copy of the images displayed on the device
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Render a snapshot of the whole deck as it physically appears
+    pub fn snapshot(&self) -> RgbImage {
+        self.framebuffer.snapshot()
+    }
+
     /// Fetch the device manufacturer string
     pub fn manufacturer(&mut self) -> Result<String, Error> {
         let s = self.device.get_manufacturer_string()?;
@@ -190,6 +406,7 @@ impl StreamDeck {
         }
 
         self.device.send_feature_report(&cmd)?;
+        self.framebuffer.invalidate();
 
         Ok(())
     }
@@ -362,83 +579,239 @@ impl StreamDeck {
 
     /// Convert an image into the device dependent format
     pub fn convert_image(&self, image: Vec<u8>) -> Result<DeviceImage, Error> {
-            // Check image dimensions
-        if image.len() != self.kind.image_size_bytes() {
-            return Err(Error::InvalidImageSize);
+        self.pipeline().convert(image)
+    }
+
+    /// Borrow the image conversion state
+    fn pipeline(&self) -> ImagePipeline<'_> {
+        ImagePipeline {
+            kind: self.kind,
+            encoder: self.encoder.as_ref(),
+            encode_options: &self.encode_options,
+            colour_profile: self.colour_profile.as_ref(),
         }
-        let image = match self.kind.image_mode() {
-            ImageMode::Bmp => image,
-            ImageMode::Jpeg => {
-                let (w, h) = self.kind.image_size();
-                encode_jpeg(&image, w, h)?
+    }
+
+    /// Clear a button to black
+    pub fn clear_key(&mut self, key: u8) -> Result<(), Error> {
+        self.fill_key(key, &Colour::rgb(0, 0, 0))
+    }
+
+    /// Clear all buttons to black
+    pub fn clear_all(&mut self) -> Result<(), Error> {
+        self.fill_all(&Colour::rgb(0, 0, 0))
+    }
+
+    /// Fill a button with a solid colour
+    ///
+    /// Unlike [StreamDeck::set_button_rgb] the colour is not blended with the key contents,
+    /// and the encoded frame is cached so repeated fills do not re-encode.
+    pub fn fill_key(&mut self, key: u8, colour: &Colour) -> Result<(), Error> {
+        let frame = self.solid_frame(colour)?;
+        self.write_button_image(key, &frame)
+    }
+
+    /// Fill all buttons with a solid colour, encoding the frame once
+    ///
+    /// The devices have no native fill or clear reports (other than [StreamDeck::reset],
+    /// which displays the logo) so each key is still written.
+    pub fn fill_all(&mut self, colour: &Colour) -> Result<(), Error> {
+        let frame = self.solid_frame(colour)?;
+        for key in 0..self.kind.keys() {
+            self.write_button_image(key, &frame)?;
+        }
+        Ok(())
+    }
+
+    /// Clear the LCD to black
+    pub fn clear_lcd(&mut self) -> Result<(), Error> {
+        self.fill_lcd(&Colour::rgb(0, 0, 0))
+    }
+
+    /// Fill the LCD with a solid colour, caching the encoded frame
+    pub fn fill_lcd(&mut self, colour: &Colour) -> Result<(), Error> {
+        let (w, h) = self.kind.lcd_size().ok_or(Error::NoLcd)?;
+        let rgb = colour.to_rgb();
+
+        let jpeg = match self.solid_lcd.take() {
+            Some((c, jpeg)) if c == rgb => jpeg,
+            _ => {
+                let mut data = RgbImage::from_pixel(w as u32, h as u32, image::Rgb(rgb)).into_raw();
+                if let Some(profile) = &self.colour_profile {
+                    profile.apply(&mut data, &ColourOrder::RGB);
+                }
+                encode_with(self.encoder.as_ref(), &data, w, h, &self.encode_options, LCD_MAX_PAYLOAD_SIZE)?
             }
         };
-        Ok(DeviceImage{ data: image })
+
+        let res = self.write_lcd_data(0, 0, w as u16, h as u16, &jpeg);
+        self.solid_lcd = Some((rgb, jpeg));
+        res?;
+
+        let image = RgbImage::from_pixel(w as u32, h as u32, image::Rgb(rgb));
+        self.framebuffer.set_lcd_region(0, 0, &image);
+
+        Ok(())
+    }
+
+    /// Fetch (or encode and cache) a solid colour key frame
+    ///
+    /// Only the last colour is cached, so fading through colours does not grow memory.
+    fn solid_frame(&mut self, colour: &Colour) -> Result<DeviceImage, Error> {
+        let rgb = colour.to_rgb();
+        if let Some((c, frame)) = &self.solid_key {
+            if *c == rgb {
+                return Ok(frame.clone());
+            }
+        }
+
+        // Solid frames are unaffected by rotation and mirroring
+        let mut data = rgb.repeat(self.kind.image_size_bytes() / 3);
+        if matches!(self.kind.image_colour_order(), ColourOrder::BGR) {
+            rgb_to_bgr(&mut data);
+        }
+        let frame = self.convert_image(data)?;
+
+        self.solid_key = Some((rgb, frame.clone()));
+        Ok(frame)
+    }
+
+    fn clear_solid_frames(&mut self) {
+        self.solid_key = None;
+        self.solid_lcd = None;
     }
 
-    /// Set a button to the provided RGB colour
+    /// Set a button to the provided colour
+    ///
+    /// Translucent colours are blended over the key's current contents
+    /// (or black where the contents are unknown).
     pub fn set_button_rgb(&mut self, key: u8, colour: &Colour) -> Result<(), Error> {
-        let mut image = vec![0u8; self.kind.image_size_bytes()];
-        let colour_order = self.kind.image_colour_order();
-
-        for i in 0..image.len() {
-            match i % 3 {
-                0 => {
-                    image[i] = match colour_order {
-                        ColourOrder::BGR => colour.b,
-                        ColourOrder::RGB => colour.r,
-                    }
-                }
-                1 => image[i] = colour.g,
-                2 => {
-                    image[i] = match colour_order {
-                        ColourOrder::BGR => colour.r,
-                        ColourOrder::RGB => colour.b,
-                    }
-                }
-                _ => unreachable!(),
-            };
+        let (w, h) = self.kind.image_size();
+        let mut image = match self.framebuffer.key(key) {
+            Some(k) if colour.a < 255 => k.clone(),
+            _ => RgbImage::new(w as u32, h as u32),
+        };
+        for p in image.pixels_mut() {
+            p.0 = colour.blend_over(p.0);
         }
-        self.write_button_image(key, &self.convert_image(image)?)?;
 
-        Ok(())
+        self.set_button_image(key, DynamicImage::ImageRgb8(image))
     }
 
     /// Set a button to the provided image
     pub fn set_button_image(&mut self, key: u8, image: DynamicImage) -> Result<(), Error> {
-        let image = apply_transform(image, self.kind.image_rotation(), self.kind.image_mirror());
-        let mut data = image.into_rgb8().into_vec();
-        if matches!(self.kind.image_colour_order(), ColourOrder::BGR) {
-            rgb_to_bgr(&mut data);
+        let image = self.pipeline().convert_dynamic(image)?;
+        self.write_button_image(key, &image)
+    }
+
+    /// Switch a button to a new image using a transition effect
+    ///
+    /// This blocks until the transition has completed.
+    pub fn transition_button(
+        &mut self,
+        key: u8,
+        image: DynamicImage,
+        transition: Transition,
+        duration: Duration,
+    ) -> Result<(), Error> {
+        transitions::play(self, transition, vec![(key, image)], duration)
+    }
+
+    /// Switch multiple buttons to new images using a transition effect, played on all keys at once
+    ///
+    /// This blocks until the transition has completed.
+    pub fn transition_buttons<I: IntoIterator<Item = (u8, DynamicImage)>>(
+        &mut self,
+        images: I,
+        transition: Transition,
+        duration: Duration,
+    ) -> Result<(), Error> {
+        transitions::play(self, transition, images.into_iter().collect(), duration)
+    }
+
+    /// Set multiple buttons to the provided images
+    ///
+    /// All images are transformed and encoded before any are written (in parallel with
+    /// the `parallel` feature), then written in key order to minimise visible tearing.
+    /// As with [StreamDeck::set_button_image] images must match the device image size.
+    pub fn set_buttons<I: IntoIterator<Item = (u8, DynamicImage)>>(&mut self, images: I) -> Result<(), Error> {
+        let mut images: Vec<_> = images.into_iter().collect();
+        if images.iter().any(|(key, _)| *key >= self.kind.keys()) {
+            return Err(Error::InvalidKeyIndex);
         }
-        self.write_button_image(key, &self.convert_image(data)?)
+        images.sort_by_key(|(key, _)| *key);
+
+        let pipeline = self.pipeline();
+
+        #[cfg(feature = "parallel")]
+        let frames: Result<Vec<_>, Error> = {
+            use rayon::prelude::*;
+            images
+                .into_par_iter()
+                .map(|(key, image)| Ok((key, pipeline.convert_dynamic(image)?)))
+                .collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let frames: Result<Vec<_>, Error> = images
+            .into_iter()
+            .map(|(key, image)| Ok((key, pipeline.convert_dynamic(image)?)))
+            .collect();
+
+        for (key, frame) in frames? {
+            self.write_button_image(key, &frame)?;
+        }
+
+        Ok(())
     }
 
     /// Sets a button to the provided text.
-    /// Will break text over \n linebreaks
+    /// Will break text over \n linebreaks, and wrap or shrink the text
+    /// as configured in the provided [TextOptions]
     #[cfg(feature = "text")]
-    pub fn set_button_text(
+    pub fn set_button_text<F: Typeface + ?Sized>(
         &mut self,
         key: u8,
-        font: &Font,
+        font: &F,
         pos: &TextPosition,
         text: &str,
         opts: &TextOptions,
     ) -> Result<(), Error> {
         let (width, height) = self.kind.image_size();
-        let background = Rgb([opts.background.r, opts.background.g, opts.background.b]);
-        let colour = Rgb([opts.foreground.r, opts.foreground.g, opts.foreground.b]);
-        let mut image = ImageBuffer::from_pixel(width as u32, height as u32, background);
-
-        match pos {
-            TextPosition::Absolute { x, y } => {
-                let mut y = *y;
-                text.to_string().split("\n").for_each(|txt| {
-                    draw_text_mut(&mut image, colour, *x, y, opts.scale, font, txt);
-                    y += (opts.scale.y * opts.line_height).round() as i32;
-                });
-            }
-        }
+        let image = text::render_text(width as u32, height as u32, font, pos, text, opts);
+
+        self.set_button_image(key, DynamicImage::ImageRgb8(image))
+    }
+
+    /// Sets a button to the provided text drawn over an image.
+    /// The image is resized to fill the key before text is drawn
+    #[cfg(feature = "text")]
+    pub fn set_button_text_over<F: Typeface + ?Sized>(
+        &mut self,
+        key: u8,
+        image: &DynamicImage,
+        font: &F,
+        pos: &TextPosition,
+        text: &str,
+        opts: &TextOptions,
+    ) -> Result<(), Error> {
+        let (width, height) = self.kind.image_size();
+        let image = text::render_text_over(image, width as u32, height as u32, font, pos, text, opts);
+
+        self.set_button_image(key, DynamicImage::ImageRgb8(image))
+    }
+
+    /// Set a button to the provided layered key face
+    pub fn set_button_face(&mut self, key: u8, face: &KeyFace) -> Result<(), Error> {
+        let (width, height) = self.kind.image_size();
+        let image = face.render(width as u32, height as u32);
+
+        self.set_button_image(key, DynamicImage::ImageRgb8(image))
+    }
+
+    /// Set a button to the provided widget
+    pub fn set_button_widget(&mut self, key: u8, widget: &impl Widget) -> Result<(), Error> {
+        let (width, height) = self.kind.image_size();
+        let image = widget.render(width as u32, height as u32);
 
         self.set_button_image(key, DynamicImage::ImageRgb8(image))
     }
@@ -454,6 +827,57 @@ impl StreamDeck {
         self.write_button_image(key, &self.load_image(image, opts)?)
     }
 
+    /// Set a button to an encoded image held in memory (for example via `include_bytes!`)
+    pub fn set_button_data(&mut self, key: u8, data: &[u8], opts: &ImageOptions) -> Result<(), Error> {
+        self.write_button_image(key, &self.load_image_data(data, opts)?)
+    }
+
+    /// Set a button to an encoded image read from a reader
+    pub fn set_button_reader<R: Read + Seek>(
+        &mut self,
+        key: u8,
+        reader: R,
+        opts: &ImageOptions,
+    ) -> Result<(), Error> {
+        self.write_button_image(key, &self.load_image_reader(reader, opts)?)
+    }
+
+    /// Set a button to an in-memory image buffer, applying the provided options
+    ///
+    /// Unlike [StreamDeck::set_button_image] the image is fitted to the key using [ImageOptions].
+    pub fn set_button_buffer(
+        &mut self,
+        key: u8,
+        image: impl Into<DynamicImage>,
+        opts: &ImageOptions,
+    ) -> Result<(), Error> {
+        self.write_button_image(key, &self.load_image_buffer(image, opts)?)
+    }
+
+    /// Set a region of the LCD (Stream Deck Plus) to the provided image file
+    ///
+    /// The image is resized to `w` by `h` pixels before being written at `x`, `y`.
+    pub fn set_lcd_file(
+        &mut self,
+        x: u16,
+        y: u16,
+        w: u16,
+        h: u16,
+        image: &str,
+        opts: &ImageOptions,
+    ) -> Result<(), Error> {
+        let data = images::load_image(
+            Path::new(image),
+            w as usize,
+            h as usize,
+            Rotation::Rot0,
+            Mirroring::None,
+            opts,
+            ColourOrder::RGB,
+        )?;
+        self.write_lcd_raw(x, y, w, h, &data)
+    }
+
     /// Load an image file into the device specific representation
     pub fn load_image(
         &self,
@@ -465,7 +889,7 @@ impl StreamDeck {
         let mirror = self.kind.image_mirror();
 
         let image = images::load_image(
-            image,
+            Path::new(image),
             x,
             y,
             rotate,
@@ -476,6 +900,33 @@ impl StreamDeck {
         self.convert_image(image)
     }
 
+    /// Load an encoded image from memory into the device specific representation
+    pub fn load_image_data(&self, data: &[u8], opts: &ImageOptions) -> Result<DeviceImage, Error> {
+        let (x, y) = self.kind.image_size();
+        self.load_image_buffer(images::decode_image_with(data, x, y, opts)?, opts)
+    }
+
+    /// Load an encoded image from a reader into the device specific representation
+    pub fn load_image_reader<R: Read + Seek>(&self, reader: R, opts: &ImageOptions) -> Result<DeviceImage, Error> {
+        let (x, y) = self.kind.image_size();
+        self.load_image_buffer(images::decode_reader_with(reader, x, y, opts)?, opts)
+    }
+
+    /// Load an image buffer (such as an [RgbImage] or [image::RgbaImage]) into the device specific representation
+    pub fn load_image_buffer(&self, image: impl Into<DynamicImage>, opts: &ImageOptions) -> Result<DeviceImage, Error> {
+        let (x, y) = self.kind.image_size();
+        let image = images::to_device_pixels(
+            image.into(),
+            x,
+            y,
+            self.kind.image_rotation(),
+            self.kind.image_mirror(),
+            opts,
+            self.kind.image_colour_order(),
+        )?;
+        self.convert_image(image)
+    }
+
     /// Transforms a key from zero-indexed left-to-right into the device-correct coordinate system
     fn translate_key_index(&self, key: u8) -> Result<u8, Error> {
         if key > self.kind.keys() {
@@ -504,10 +955,51 @@ impl StreamDeck {
     /// Writes an image to a button
     /// Image at this point in correct dimensions and in device native colour order.
     pub fn write_button_image(&mut self, key: u8, image: &DeviceImage) -> Result<(), Error> {
-        self.write_button_image_raw(key, &image.data)
+        self.write_button_data(key, &image.data)?;
+
+        match &image.pixels {
+            Some(p) => self.framebuffer.set_key_native(key, p.clone()),
+            None => self.framebuffer.invalidate_key(key),
+        }
+
+        Ok(())
     }
 
+    /// Writes device native image data to a button
+    ///
+    /// The key contents are marked as unknown in the [Framebuffer] mirror.
     pub fn write_button_image_raw(&mut self, key: u8, image: &[u8]) -> Result<(), Error> {
+        self.write_button_data(key, image)?;
+        self.framebuffer.invalidate_key(key);
+
+        Ok(())
+    }
+
+    /// Writes pre-encoded JPEG data to a button (V2 devices)
+    ///
+    /// The image must already be rotated and mirrored for the device (see
+    /// [prebake_dir]), its dimensions are checked against the device image size.
+    /// Colour profiles are not applied, and the key contents are marked as
+    /// unknown in the [Framebuffer] mirror to avoid decoding the image.
+    pub fn set_button_jpeg(&mut self, key: u8, jpeg: &[u8]) -> Result<(), Error> {
+        if self.kind.image_mode() != ImageMode::Jpeg {
+            return Err(Error::UnsupportedImageMode);
+        }
+
+        // Quarter turns swap the dimensions of the device native image
+        let (w, h) = self.kind.image_size();
+        let expected = match self.kind.image_rotation() {
+            Rotation::Rot90 | Rotation::Rot270 => (h, w),
+            Rotation::Rot0 | Rotation::Rot180 => (w, h),
+        };
+        if jpeg_dimensions(jpeg)? != expected {
+            return Err(Error::InvalidImageSize);
+        }
+
+        self.write_button_image_raw(key, jpeg)
+    }
+
+    fn write_button_data(&mut self, key: u8, image: &[u8]) -> Result<(), Error> {
         let key = self.translate_key_index(key)?;
 
         let mut buf = vec![0u8; self.kind.image_report_len()];
@@ -597,24 +1089,80 @@ impl StreamDeck {
         }
     }
 
+    /// Set a region of the LCD (Stream Deck Plus) to the provided image
+    pub fn set_lcd_image(&mut self, x: u16, y: u16, image: DynamicImage) -> Result<(), Error> {
+        let image = image.into_rgb8();
+        let (w, h) = image.dimensions();
+        self.write_lcd_raw(x, y, w as u16, h as u16, image.as_raw())
+    }
+
+    /// Set an LCD segment (the area above each knob) to the provided widget
+    pub fn set_lcd_widget(&mut self, segment: u8, widget: &impl Widget) -> Result<(), Error> {
+        let (w, h) = self.kind.lcd_segment_size().ok_or(Error::NoLcd)?;
+        if segment >= self.kind.knobs() {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        let image = widget.render(w as u32, h as u32);
+        self.set_lcd_image(segment as u16 * w as u16, 0, DynamicImage::ImageRgb8(image))
+    }
+
     pub fn write_lcd_raw(&mut self, x: u16, y: u16, w: u16, h: u16, data: &[u8]) -> Result<(), Error> {
         assert!(data.len() == (w as usize * h as usize * 3));
-        let jpeg = encode_jpeg(data, w as usize, h as usize)?;
-        self.write_lcd_jpeg(x, y, w, h, &jpeg)
+
+        let mut corrected = data.to_vec();
+        if let Some(profile) = &self.colour_profile {
+            profile.apply(&mut corrected, &ColourOrder::RGB);
+        }
+
+        let jpeg = encode_with(
+            self.encoder.as_ref(),
+            &corrected,
+            w as usize,
+            h as usize,
+            &self.encode_options,
+            LCD_MAX_PAYLOAD_SIZE,
+        )?;
+        self.write_lcd_data(x, y, w, h, &jpeg)?;
+
+        if let Some(image) = RgbImage::from_raw(w as u32, h as u32, data.to_vec()) {
+            self.framebuffer.set_lcd_region(x as u32, y as u32, &image);
+        }
+
+        Ok(())
     }
 
+    /// Writes JPEG data to the LCD at `x`, `y` with the provided size
+    ///
+    /// The region is marked as unknown in the [Framebuffer] mirror rather than decoding the image.
     pub fn write_lcd_jpeg(&mut self, x: u16, y: u16, w: u16, h: u16, jpeg: &[u8]) -> Result<(), Error> {
-        const MAX_PACKET_SIZE: usize = 1024;
-        const PACKET_HEADER_LENGTH: usize = 16;
-        const MAX_PAYLOAD_SIZE: usize = MAX_PACKET_SIZE - PACKET_HEADER_LENGTH;
+        self.write_lcd_data(x, y, w, h, jpeg)?;
+        self.framebuffer.invalidate_lcd_region(x as u32, y as u32, w as u32, h as u32);
+
+        Ok(())
+    }
+
+    /// Writes pre-encoded JPEG data to the LCD at `x`, `y`
+    ///
+    /// The image dimensions are read from the JPEG header and checked against the LCD bounds.
+    pub fn set_lcd_jpeg(&mut self, x: u16, y: u16, jpeg: &[u8]) -> Result<(), Error> {
+        let (lw, lh) = self.kind.lcd_size().ok_or(Error::NoLcd)?;
+        let (w, h) = jpeg_dimensions(jpeg)?;
+        if x as usize + w > lw || y as usize + h > lh {
+            return Err(Error::InvalidImageSize);
+        }
+
+        self.write_lcd_jpeg(x, y, w as u16, h as u16, jpeg)
+    }
 
-        let mut buf = vec![0u8; MAX_PACKET_SIZE];
+    fn write_lcd_data(&mut self, x: u16, y: u16, w: u16, h: u16, jpeg: &[u8]) -> Result<(), Error> {
+        let mut buf = vec![0u8; LCD_MAX_PACKET_SIZE];
 
         let mut remaining = jpeg.len();
         let mut pos: usize = 0;
         let mut part: u16  = 0;
         while remaining > 0 {
-            let byte_count = remaining.min(MAX_PAYLOAD_SIZE);
+            let byte_count = remaining.min(LCD_MAX_PAYLOAD_SIZE);
 
             buf[0] = 0x02;
             buf[1] = 0x0c;
@@ -626,14 +1174,14 @@ impl StreamDeck {
             buf[7] = w.to_le_bytes()[1];
             buf[8] = h.to_le_bytes()[0];
             buf[9] = h.to_le_bytes()[1];
-            buf[10] = if remaining <= MAX_PAYLOAD_SIZE { 1 } else { 0 };
+            buf[10] = if remaining <= LCD_MAX_PAYLOAD_SIZE { 1 } else { 0 };
             buf[11] = part.to_le_bytes()[0];
             buf[12] = part.to_le_bytes()[1];
             buf[13] = byte_count.to_le_bytes()[0];
             buf[14] = byte_count.to_le_bytes()[1];
 
             for i in 0..byte_count {
-                buf[PACKET_HEADER_LENGTH + i] = jpeg[pos];
+                buf[LCD_PACKET_HEADER_LENGTH + i] = jpeg[pos];
                 pos += 1;
             }
             self.device.write(&buf)?;
@@ -668,47 +1216,6 @@ pub enum TouchInput {
     Swipe { x0: u16, y0: u16, x1: u16, y1: u16},
 }
 
-/// TextPosition is how to position text via set_button_text
-pub enum TextPosition {
-    /// Absolute positioning
-    Absolute { x: i32, y: i32 },
-}
-
-/// Text Options provide values for text buttons
-#[cfg(feature = "text")]
-pub struct TextOptions {
-    foreground: Colour,
-    background: Colour,
-    scale: Scale,
-    line_height: f32,
-}
-
-#[cfg(feature = "text")]
-impl TextOptions {
-    pub fn new(foreground: Colour, background: Colour, scale: Scale, line_height: f32) -> Self {
-        TextOptions {
-            foreground,
-            background,
-            scale,
-            line_height,
-        }
-    }
-}
-
-#[cfg(feature = "text")]
-impl Default for TextOptions {
-    /// default is white text on a black background, with 15 pixel high text
-    /// and 1.1x the line height.
-    fn default() -> Self {
-        TextOptions {
-            foreground: Colour::from_str("FFFFFF").unwrap(),
-            background: Colour::from_str("000000").unwrap(),
-            scale: Scale { x: 15.0, y: 15.0 },
-            line_height: 1.1,
-        }
-    }
-}
-
 // Convert RGB image data to BGR
 fn rgb_to_bgr(data: &mut Vec<u8>) {
     for chunk in data.chunks_exact_mut(3) {
@@ -0,0 +1,171 @@
+//! Scrolling marquee text
+//!
+//! A [Marquee] lays text out once into a wide strip, then scrolls it across a
+//! [Target] (a key, a row of keys including the gaps between them, or the LCD)
+//! at a configurable speed. Text that fits the target is displayed centred
+//! without scrolling.
+
+use std::time::Duration;
+
+use image::{Rgb, RgbImage};
+
+use crate::info::Kind;
+use crate::surface::Target;
+use crate::text::{render_text, HAlign, TextOptions, TextPosition, Typeface, VAlign};
+use crate::{Error, StreamDeck};
+
+/// Scrolling text across keys or the LCD
+pub struct Marquee {
+    target: Target,
+    width: u32,
+    height: u32,
+    strip: RgbImage,
+    background: Rgb<u8>,
+    speed: f32,
+    gap: u32,
+}
+
+impl Marquee {
+    /// Lay out a single line of text for scrolling across the target
+    ///
+    /// Wrapping and shrink to fit are ignored, the text is vertically centred.
+    pub fn new<F: Typeface + ?Sized>(
+        kind: Kind,
+        target: Target,
+        font: &F,
+        text: &str,
+        opts: &TextOptions,
+    ) -> Result<Self, Error> {
+        let (width, height) = target.size(kind)?;
+        let opts = TextOptions {
+            wrap: false,
+            min_scale: None,
+            ..opts.clone()
+        };
+
+        // Outlines and shadows extend beyond the glyph advance
+        let extra = opts.outline.as_ref().map(|o| o.width).unwrap_or(0) * 2
+            + opts.shadow.as_ref().map(|s| s.offset.0.unsigned_abs()).unwrap_or(0);
+        let text_width = font.text_width(text, opts.scale).ceil() as u32 + extra;
+
+        let pos = TextPosition::Aligned {
+            horizontal: HAlign::Centre,
+            vertical: VAlign::Centre,
+            padding: 0,
+        };
+        let strip = render_text(text_width.max(width), height, font, &pos, text, &opts);
+
+        Ok(Self {
+            target,
+            width,
+            height,
+            strip,
+            background: Rgb(opts.background.to_rgb()),
+            speed: 40.0,
+            gap: width / 2,
+        })
+    }
+
+    /// Set the scroll speed in pixels per second
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Set the space between the end of the text and the next repeat, in pixels
+    pub fn with_gap(mut self, gap: u32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    /// Check whether the text scrolls (it is wider than the target)
+    pub fn scrolls(&self) -> bool {
+        self.strip.width() > self.width
+    }
+
+    /// Time taken to scroll through the text once
+    pub fn period(&self) -> Duration {
+        if !self.scrolls() || self.speed <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f32((self.strip.width() + self.gap) as f32 / self.speed)
+    }
+
+    /// Render the frame displayed `elapsed` time after the marquee started
+    pub fn frame(&self, elapsed: Duration) -> RgbImage {
+        if !self.scrolls() {
+            return self.strip.clone();
+        }
+
+        let period = self.strip.width() + self.gap;
+        let offset = (elapsed.as_secs_f32() * self.speed).max(0.0) as u64 % period as u64;
+
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let sx = ((offset + x as u64) % period as u64) as u32;
+            if sx < self.strip.width() {
+                *self.strip.get_pixel(sx, y)
+            } else {
+                self.background
+            }
+        })
+    }
+
+    /// Write the frame for `elapsed` time to the device
+    pub fn write(&self, deck: &mut StreamDeck, elapsed: Duration) -> Result<(), Error> {
+        self.target.write(deck, &self.frame(elapsed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::text::Scale;
+
+    /// Typeface drawing each character as a filled block
+    struct Blocks;
+
+    impl Typeface for Blocks {
+        fn text_width(&self, text: &str, scale: Scale) -> f32 {
+            text.chars().count() as f32 * scale.x
+        }
+
+        fn draw_line(&self, image: &mut RgbImage, colour: Rgb<u8>, x: i32, y: i32, scale: Scale, text: &str) {
+            let w = (text.chars().count() as f32 * scale.x) as i32;
+            for py in y.max(0)..(y + scale.y as i32).min(image.height() as i32) {
+                for px in x.max(0)..(x + w).min(image.width() as i32) {
+                    image.put_pixel(px as u32, py as u32, colour);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_across_row() {
+        let opts = TextOptions::default().with_scale(Scale::uniform(10.0));
+
+        // 30 characters is wider than two Mini keys
+        let text = "x".repeat(30);
+        let marquee = Marquee::new(Kind::Mini, Target::Keys { first: 0, count: 2 }, &Blocks, &text, &opts)
+            .unwrap()
+            .with_speed(100.0)
+            .with_gap(50);
+        assert!(marquee.scrolls());
+
+        let (w, _) = Target::Keys { first: 0, count: 2 }.size(Kind::Mini).unwrap();
+        let frame = marquee.frame(Duration::ZERO);
+        assert_eq!(frame.width(), w);
+        assert_eq!(frame.get_pixel(0, 40).0, [255, 255, 255]);
+
+        // After 3 seconds the text has scrolled 300px, into the gap
+        let frame = marquee.frame(Duration::from_secs(3));
+        assert_eq!(frame.get_pixel(0, 40).0, [0, 0, 0]);
+        assert_eq!(marquee.period(), Duration::from_secs_f32(3.5));
+
+        let short = Marquee::new(Kind::Mini, Target::Key(0), &Blocks, "x", &opts).unwrap();
+        assert!(!short.scrolls());
+    }
+}
@@ -0,0 +1,288 @@
+//! Pages and folders
+//!
+//! A [Page] is a named set of key bindings, each with a [KeyFace] and an
+//! [Action]. Folder keys push a sub-page onto a [PageStack], and a reserved
+//! back key returns to the previous page. The stack re-renders the whole deck
+//! on navigation and routes presses to the current page's bindings, returning
+//! application actions to the caller.
+
+use std::collections::{BTreeMap, HashMap};
+
+use image::{DynamicImage, RgbImage};
+
+use crate::face::KeyFace;
+use crate::images::Colour;
+use crate::{Error, StreamDeck};
+
+/// Action bound to a key
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action<A> {
+    /// Display only
+    None,
+    /// Open the named page
+    Folder(String),
+    /// Return to the previous page
+    Back,
+    /// Application defined action, returned to the caller when pressed
+    Custom(A),
+}
+
+/// Key binding on a page
+#[derive(Clone)]
+pub struct KeyBinding<A> {
+    pub face: KeyFace,
+    pub action: Action<A>,
+}
+
+/// Named page of key bindings
+#[derive(Clone)]
+pub struct Page<A> {
+    name: String,
+    keys: BTreeMap<u8, KeyBinding<A>>,
+}
+
+impl<A> Page<A> {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Bind a key on the page
+    pub fn key(mut self, key: u8, face: KeyFace, action: Action<A>) -> Self {
+        self.keys.insert(key, KeyBinding { face, action });
+        self
+    }
+
+    /// Bind a folder key opening the named page
+    pub fn folder(self, key: u8, face: KeyFace, page: &str) -> Self {
+        self.key(key, face, Action::Folder(page.to_string()))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn binding(&self, key: u8) -> Option<&KeyBinding<A>> {
+        self.keys.get(&key)
+    }
+}
+
+/// Result of a key press on a [PageStack]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Press<A> {
+    /// The key has no action
+    Ignored,
+    /// The current page changed and the deck must be redrawn
+    Navigated,
+    /// An application action was pressed
+    Action(A),
+}
+
+/// Navigable stack of pages
+pub struct PageStack<A> {
+    pages: HashMap<String, Page<A>>,
+    stack: Vec<String>,
+    back_key: u8,
+    back_face: KeyFace,
+    last_states: Vec<u8>,
+}
+
+impl<A: Clone> PageStack<A> {
+    /// Create a page stack starting at the root page
+    ///
+    /// `back_key` is reserved on sub-pages for returning to the previous page.
+    pub fn new(root: Page<A>, back_key: u8) -> Self {
+        let name = root.name.clone();
+        let mut pages = HashMap::new();
+        pages.insert(name.clone(), root);
+
+        Self {
+            pages,
+            stack: vec![name],
+            back_key,
+            back_face: KeyFace::new().background(Colour::rgb(48, 48, 48)),
+            last_states: vec![],
+        }
+    }
+
+    /// Add a page that can be opened by folder keys
+    pub fn add_page(&mut self, page: Page<A>) {
+        self.pages.insert(page.name.clone(), page);
+    }
+
+    /// Set the face displayed on the back key of sub-pages
+    pub fn with_back_face(mut self, face: KeyFace) -> Self {
+        self.back_face = face;
+        self
+    }
+
+    /// Fetch the current page
+    pub fn current(&self) -> &Page<A> {
+        // The stack always contains the root page
+        &self.pages[self.stack.last().unwrap()]
+    }
+
+    /// Names of the pages on the stack, from the root to the current page
+    pub fn path(&self) -> &[String] {
+        &self.stack
+    }
+
+    /// Open a page on top of the stack
+    pub fn push(&mut self, name: &str) -> Result<(), Error> {
+        if !self.pages.contains_key(name) {
+            return Err(Error::UnknownPage(name.to_string()));
+        }
+        self.stack.push(name.to_string());
+        Ok(())
+    }
+
+    /// Return to the previous page, returns false at the root page
+    pub fn pop(&mut self) -> bool {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+            return true;
+        }
+        false
+    }
+
+    /// Binding for a key on the current page, including the back key on sub-pages
+    fn action(&self, key: u8) -> Option<Action<A>> {
+        if self.stack.len() > 1 && key == self.back_key {
+            return Some(Action::Back);
+        }
+        self.current().binding(key).map(|b| b.action.clone())
+    }
+
+    /// Handle a key press, navigating or returning the bound action
+    pub fn press(&mut self, key: u8) -> Result<Press<A>, Error> {
+        let action = self.action(key);
+        self.apply(action)
+    }
+
+    fn apply(&mut self, action: Option<Action<A>>) -> Result<Press<A>, Error> {
+        match action {
+            Some(Action::Folder(name)) => {
+                self.push(&name)?;
+                Ok(Press::Navigated)
+            }
+            Some(Action::Back) if self.pop() => Ok(Press::Navigated),
+            Some(Action::Custom(a)) => Ok(Press::Action(a)),
+            _ => Ok(Press::Ignored),
+        }
+    }
+
+    /// Render the images for every key on the current page
+    pub fn render(&self, width: u32, height: u32, keys: u8) -> Vec<(u8, RgbImage)> {
+        (0..keys)
+            .map(|key| {
+                let image = if self.stack.len() > 1 && key == self.back_key {
+                    self.back_face.render(width, height)
+                } else {
+                    match self.current().binding(key) {
+                        Some(b) => b.face.render(width, height),
+                        None => RgbImage::new(width, height),
+                    }
+                };
+                (key, image)
+            })
+            .collect()
+    }
+
+    /// Redraw the whole deck with the current page
+    pub fn draw(&self, deck: &mut StreamDeck) -> Result<(), Error> {
+        let (w, h) = deck.image_size();
+        let images = self.render(w as u32, h as u32, deck.kind().keys());
+        deck.set_buttons(images.into_iter().map(|(k, i)| (k, DynamicImage::ImageRgb8(i))))
+    }
+
+    /// Route newly pressed keys from button states (as returned by
+    /// [StreamDeck::read_buttons]), redrawing on navigation and returning any actions
+    pub fn handle_buttons(&mut self, deck: &mut StreamDeck, states: &[u8]) -> Result<Vec<A>, Error> {
+        let (actions, navigated) = self.route(states)?;
+        if navigated {
+            self.draw(deck)?;
+        }
+
+        Ok(actions)
+    }
+
+    /// Apply newly pressed keys, returning the actions and whether the page changed
+    fn route(&mut self, states: &[u8]) -> Result<(Vec<A>, bool), Error> {
+        let pressed: Vec<u8> = (0..states.len())
+            .filter(|&key| states[key] != 0 && self.last_states.get(key).map(|s| *s == 0).unwrap_or(true))
+            .map(|key| key as u8)
+            .collect();
+        self.last_states = states.to_vec();
+
+        // Resolve every press against the page current when the report arrived
+        let bound: Vec<_> = pressed.into_iter().map(|key| self.action(key)).collect();
+
+        let mut actions = vec![];
+        let mut navigated = false;
+        for action in bound {
+            match self.apply(action)? {
+                Press::Navigated => navigated = true,
+                Press::Action(a) => actions.push(a),
+                Press::Ignored => (),
+            }
+        }
+
+        Ok((actions, navigated))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn navigate_pages() {
+        let root = Page::new("root")
+            .folder(0, KeyFace::new(), "media")
+            .key(4, KeyFace::new(), Action::Custom("mute"));
+        let media = Page::new("media")
+            .key(1, KeyFace::new().background(Colour::rgb(0, 255, 0)), Action::Custom("play"))
+            .key(4, KeyFace::new(), Action::Custom("hidden by back key"));
+
+        let mut stack = PageStack::new(root, 4);
+        stack.add_page(media);
+
+        assert_eq!(stack.press(4).unwrap(), Press::Action("mute"));
+        assert_eq!(stack.press(0).unwrap(), Press::Navigated);
+        assert_eq!(stack.path(), &["root".to_string(), "media".to_string()]);
+        assert_eq!(stack.press(1).unwrap(), Press::Action("play"));
+
+        let images = stack.render(8, 8, 6);
+        assert_eq!(images[1].1.get_pixel(0, 0).0, [0, 255, 0]);
+        assert_eq!(images[4].1.get_pixel(0, 0).0, [48, 48, 48]);
+
+        assert_eq!(stack.press(4).unwrap(), Press::Navigated);
+        assert_eq!(stack.current().name(), "root");
+        assert!(stack.push("missing").is_err());
+    }
+
+    #[test]
+    fn route_reports() {
+        let root = Page::new("root")
+            .folder(0, KeyFace::new(), "media")
+            .folder(1, KeyFace::new(), "missing")
+            .key(4, KeyFace::new(), Action::Custom("mute"));
+        let mut stack = PageStack::new(root, 4);
+        stack.add_page(Page::new("media"));
+
+        // Presses in one report resolve against the root page, so key 4 is not the back key
+        let mut states = vec![0u8; 6];
+        states[0] = 1;
+        states[4] = 1;
+        assert_eq!(stack.route(&states).unwrap(), (vec!["mute"], true));
+        assert_eq!(stack.current().name(), "media");
+
+        // A failed press is not retried while the key is held
+        stack.pop();
+        states = vec![0, 1, 0, 0, 0, 0];
+        assert!(stack.route(&states).is_err());
+        assert_eq!(stack.route(&states).unwrap(), (vec![], false));
+    }
+}
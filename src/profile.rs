@@ -0,0 +1,423 @@
+//! Per-device colour correction
+//!
+//! Different device generations display the same RGB values noticeably
+//! differently. A [ColourProfile] corrects for this with a gamma adjustment,
+//! white point gains, and optional 1D and 3D lookup tables, applied to images
+//! just before they are converted to the device format. Profiles can be
+//! registered per [Kind] or per device serial in [ColourProfiles].
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::info::{ColourOrder, Kind};
+use crate::Error;
+
+/// Input range of a lookup table, per channel
+#[derive(Debug, Clone, PartialEq)]
+struct Domain {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Domain {
+    fn new(min: [f32; 3], max: [f32; 3]) -> Result<Self, Error> {
+        if (0..3).any(|c| max[c] <= min[c]) {
+            return Err(Error::InvalidLut("domain max must be greater than min".to_string()));
+        }
+        Ok(Self { min, max })
+    }
+
+    /// Map an input value into the 0.0..=1.0 table range
+    fn normalise(&self, channel: usize, v: f32) -> f32 {
+        ((v - self.min[channel]) / (self.max[channel] - self.min[channel])).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for Domain {
+    fn default() -> Self {
+        Self {
+            min: [0.0; 3],
+            max: [1.0; 3],
+        }
+    }
+}
+
+/// Per channel 1D lookup table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut1d {
+    entries: Vec<[f32; 3]>,
+    domain: Domain,
+}
+
+impl Lut1d {
+    /// Create a 1D LUT from evenly spaced entries mapping 0.0..=1.0 inputs
+    pub fn new(entries: Vec<[f32; 3]>) -> Result<Self, Error> {
+        if entries.len() < 2 {
+            return Err(Error::InvalidLut("1D LUT requires at least 2 entries".to_string()));
+        }
+        Ok(Self {
+            entries,
+            domain: Domain::default(),
+        })
+    }
+
+    /// Set the input range covered by the entries
+    pub fn with_domain(mut self, min: [f32; 3], max: [f32; 3]) -> Result<Self, Error> {
+        self.domain = Domain::new(min, max)?;
+        Ok(self)
+    }
+
+    /// Parse a 1D LUT in the `.cube` format
+    pub fn parse_cube(s: &str) -> Result<Self, Error> {
+        match parse_cube(s)? {
+            Cube::Lut1d(l) => Ok(l),
+            Cube::Lut3d(_) => Err(Error::InvalidLut("expected LUT_1D_SIZE".to_string())),
+        }
+    }
+
+    /// Load a 1D LUT from a `.cube` file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse_cube(&std::fs::read_to_string(path)?)
+    }
+
+    fn lookup(&self, channel: usize, v: f32) -> f32 {
+        let n = self.entries.len() - 1;
+        let p = self.domain.normalise(channel, v) * n as f32;
+        let i = (p.floor() as usize).min(n - 1);
+        let t = p - i as f32;
+        let (a, b) = (self.entries[i][channel], self.entries[i + 1][channel]);
+        a + (b - a) * t
+    }
+}
+
+/// 3D lookup table, sampled with trilinear interpolation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    size: usize,
+    entries: Vec<[f32; 3]>,
+    domain: Domain,
+}
+
+impl Lut3d {
+    /// Create a 3D LUT of `size`^3 entries, with red varying fastest
+    pub fn new(size: usize, entries: Vec<[f32; 3]>) -> Result<Self, Error> {
+        if size < 2 || entries.len() != size * size * size {
+            return Err(Error::InvalidLut(format!(
+                "3D LUT of size {} requires {} entries",
+                size,
+                size * size * size
+            )));
+        }
+        Ok(Self {
+            size,
+            entries,
+            domain: Domain::default(),
+        })
+    }
+
+    /// Set the input range covered by the entries
+    pub fn with_domain(mut self, min: [f32; 3], max: [f32; 3]) -> Result<Self, Error> {
+        self.domain = Domain::new(min, max)?;
+        Ok(self)
+    }
+
+    /// Parse a 3D LUT in the `.cube` format
+    pub fn parse_cube(s: &str) -> Result<Self, Error> {
+        match parse_cube(s)? {
+            Cube::Lut3d(l) => Ok(l),
+            Cube::Lut1d(_) => Err(Error::InvalidLut("expected LUT_3D_SIZE".to_string())),
+        }
+    }
+
+    /// Load a 3D LUT from a `.cube` file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse_cube(&std::fs::read_to_string(path)?)
+    }
+
+    fn get(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.entries[r + self.size * (g + self.size * b)]
+    }
+
+    fn lookup(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let n = self.size - 1;
+        let mut idx = [0usize; 3];
+        let mut t = [0f32; 3];
+        for c in 0..3 {
+            let p = self.domain.normalise(c, rgb[c]) * n as f32;
+            idx[c] = (p.floor() as usize).min(n - 1);
+            t[c] = p - idx[c] as f32;
+        }
+
+        let [r, g, b] = idx;
+        let mut out = [0f32; 3];
+        for (c, o) in out.iter_mut().enumerate() {
+            let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+            let c00 = lerp(self.get(r, g, b)[c], self.get(r + 1, g, b)[c], t[0]);
+            let c10 = lerp(self.get(r, g + 1, b)[c], self.get(r + 1, g + 1, b)[c], t[0]);
+            let c01 = lerp(self.get(r, g, b + 1)[c], self.get(r + 1, g, b + 1)[c], t[0]);
+            let c11 = lerp(self.get(r, g + 1, b + 1)[c], self.get(r + 1, g + 1, b + 1)[c], t[0]);
+            *o = lerp(lerp(c00, c10, t[1]), lerp(c01, c11, t[1]), t[2]);
+        }
+        out
+    }
+}
+
+enum Cube {
+    Lut1d(Lut1d),
+    Lut3d(Lut3d),
+}
+
+/// Parse a `.cube` LUT file (as used by Resolve and most colour tools)
+fn parse_cube(s: &str) -> Result<Cube, Error> {
+    let invalid = |m: String| Error::InvalidLut(m);
+
+    let mut size_1d = None;
+    let mut size_3d = None;
+    let mut min = [0f32; 3];
+    let mut max = [1f32; 3];
+    let mut entries = vec![];
+
+    let triple = |args: &[&str]| -> Result<[f32; 3], Error> {
+        if args.len() != 3 {
+            return Err(invalid(format!("expected 3 values: {}", args.join(" "))));
+        }
+        let mut v = [0f32; 3];
+        for (o, a) in v.iter_mut().zip(args) {
+            *o = a.parse().map_err(|_| invalid(format!("invalid value: {}", a)))?;
+        }
+        Ok(v)
+    };
+
+    for line in s.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args[0] {
+            "TITLE" => (),
+            "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                let size: usize = args
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| invalid(format!("invalid size: {}", line)))?;
+                if args[0] == "LUT_1D_SIZE" {
+                    size_1d = Some(size);
+                } else {
+                    size_3d = Some(size);
+                }
+            }
+            "DOMAIN_MIN" => min = triple(&args[1..])?,
+            "DOMAIN_MAX" => max = triple(&args[1..])?,
+            k if k.chars().all(|c| c.is_ascii_uppercase() || c == '_') => {
+                return Err(invalid(format!("unsupported keyword: {}", k)));
+            }
+            _ => entries.push(triple(&args)?),
+        }
+    }
+
+    // The domain sets the input range, entries are output values
+    match (size_1d, size_3d) {
+        (Some(n), None) if entries.len() == n => Ok(Cube::Lut1d(Lut1d::new(entries)?.with_domain(min, max)?)),
+        (None, Some(n)) => Ok(Cube::Lut3d(Lut3d::new(n, entries)?.with_domain(min, max)?)),
+        (None, None) => Err(invalid("missing LUT size".to_string())),
+        _ => Err(invalid("invalid LUT size or entries".to_string())),
+    }
+}
+
+/// Colour correction applied to images before they are sent to a device
+///
+/// Corrections are applied in order: white point gains, gamma, 1D LUT, then 3D LUT.
+/// The per channel steps are precomputed into tables so only the 3D LUT
+/// (if any) is evaluated per pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColourProfile {
+    gamma: f32,
+    white_point: [f32; 3],
+    lut_1d: Option<Lut1d>,
+    lut_3d: Option<Lut3d>,
+    curves: Vec<[u8; 3]>,
+}
+
+impl ColourProfile {
+    /// Create an identity profile
+    pub fn new() -> Self {
+        let mut p = Self {
+            gamma: 1.0,
+            white_point: [1.0; 3],
+            lut_1d: None,
+            lut_3d: None,
+            curves: vec![],
+        };
+        p.update();
+        p
+    }
+
+    /// Set the gamma adjustment, values are raised to `1 / gamma`
+    /// so values above 1.0 brighten mid tones
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.gamma = gamma.max(0.01);
+        self.update();
+        self
+    }
+
+    /// Set the white point as per channel gains (0.0 to 1.0)
+    pub fn white_point(mut self, r: f32, g: f32, b: f32) -> Self {
+        self.white_point = [r, g, b];
+        self.update();
+        self
+    }
+
+    /// Set a per channel 1D lookup table
+    pub fn lut_1d(mut self, lut: Lut1d) -> Self {
+        self.lut_1d = Some(lut);
+        self.update();
+        self
+    }
+
+    /// Set a 3D lookup table
+    pub fn lut_3d(mut self, lut: Lut3d) -> Self {
+        self.lut_3d = Some(lut);
+        self
+    }
+
+    /// Rebuild the per channel tables
+    fn update(&mut self) {
+        self.curves = (0..=255u8)
+            .map(|i| {
+                let mut out = [0u8; 3];
+                for (c, o) in out.iter_mut().enumerate() {
+                    let v = (i as f32 / 255.0 * self.white_point[c]).clamp(0.0, 1.0);
+                    let mut v = v.powf(1.0 / self.gamma);
+                    if let Some(lut) = &self.lut_1d {
+                        v = lut.lookup(c, v);
+                    }
+                    *o = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+                out
+            })
+            .collect();
+    }
+
+    /// Correct a single RGB value
+    pub fn correct(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let mut out = [0u8; 3];
+        for c in 0..3 {
+            out[c] = self.curves[rgb[c] as usize][c];
+        }
+
+        if let Some(lut) = &self.lut_3d {
+            let v = lut.lookup(out.map(|v| v as f32 / 255.0));
+            out = v.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+
+        out
+    }
+
+    /// Correct packed pixel data in the provided colour order
+    pub fn apply(&self, data: &mut [u8], order: &ColourOrder) {
+        for p in data.chunks_exact_mut(3) {
+            match order {
+                ColourOrder::RGB => {
+                    let c = self.correct([p[0], p[1], p[2]]);
+                    p.copy_from_slice(&c);
+                }
+                ColourOrder::BGR => {
+                    let [r, g, b] = self.correct([p[2], p[1], p[0]]);
+                    p.copy_from_slice(&[b, g, r]);
+                }
+            }
+        }
+    }
+}
+
+impl Default for ColourProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Registry of colour profiles by device kind or serial
+///
+/// Serial profiles take precedence over kind profiles.
+#[derive(Debug, Clone, Default)]
+pub struct ColourProfiles {
+    kinds: HashMap<Kind, ColourProfile>,
+    serials: HashMap<String, ColourProfile>,
+}
+
+impl ColourProfiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a profile for all devices of a kind
+    pub fn set_kind(&mut self, kind: Kind, profile: ColourProfile) {
+        self.kinds.insert(kind, profile);
+    }
+
+    /// Register a profile for the device with the provided serial
+    pub fn set_serial(&mut self, serial: &str, profile: ColourProfile) {
+        self.serials.insert(serial.to_string(), profile);
+    }
+
+    /// Find the profile for a device
+    pub fn lookup(&self, kind: Kind, serial: Option<&str>) -> Option<&ColourProfile> {
+        serial
+            .and_then(|s| self.serials.get(s))
+            .or_else(|| self.kinds.get(&kind))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profile_curves() {
+        let p = ColourProfile::new();
+        assert_eq!(p.correct([10, 128, 250]), [10, 128, 250]);
+
+        let p = ColourProfile::new().white_point(1.0, 0.5, 0.0);
+        assert_eq!(p.correct([255, 255, 255]), [255, 128, 0]);
+
+        // Gamma brightens mid tones but keeps end points
+        let p = ColourProfile::new().gamma(2.2);
+        let [v, _, _] = p.correct([128, 0, 255]);
+        assert!(v > 128);
+        assert_eq!(p.correct([0, 0, 255])[1..], [0, 255]);
+
+        let mut data = vec![0, 0, 255];
+        ColourProfile::new().white_point(0.0, 1.0, 1.0).apply(&mut data, &ColourOrder::BGR);
+        assert_eq!(data, vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn parse_cube_luts() {
+        let identity = "TITLE \"identity\"\nLUT_3D_SIZE 2\n\
+            0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        let p = ColourProfile::new().lut_3d(Lut3d::parse_cube(identity).unwrap());
+        assert_eq!(p.correct([12, 200, 99]), [12, 200, 99]);
+
+        // Swap red and blue
+        let swap = "LUT_3D_SIZE 2\n\
+            0 0 0\n0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+        let p = ColourProfile::new().lut_3d(Lut3d::parse_cube(swap).unwrap());
+        assert_eq!(p.correct([255, 10, 0]), [0, 10, 255]);
+
+        // Invert with a 1D LUT
+        let invert = "# comment\nLUT_1D_SIZE 2\n1 1 1\n0 0 0\n";
+        let p = ColourProfile::new().lut_1d(Lut1d::parse_cube(invert).unwrap());
+        assert_eq!(p.correct([0, 255, 55]), [255, 0, 200]);
+
+        // The domain maps inputs, so 0.0..=1.0 covers the first half of this table
+        let domain = "LUT_1D_SIZE 3\nDOMAIN_MAX 2 2 2\n1 1 1\n0 0 0\n0 0 0\n";
+        let p = ColourProfile::new().lut_1d(Lut1d::parse_cube(domain).unwrap());
+        assert_eq!(p.correct([0, 255, 128]), [255, 0, 127]);
+        assert!(Lut1d::parse_cube("LUT_1D_SIZE 2\nDOMAIN_MIN 1 1 1\n0 0 0\n1 1 1\n").is_err());
+
+        assert!(Lut3d::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut3d::parse_cube(invert).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! QR code rendering
+//!
+//! [Qr] encodes a string (a URL, or a Wi-Fi network using [Qr::wifi]) and
+//! renders it black on white with a quiet zone, using whole pixel modules for
+//! crisp edges. The highest error correction level that still gives modules
+//! of at least [Qr::min_module_size] pixels is selected, so codes remain
+//! scannable at key resolution. Codes can be written to any [Target], but on
+//! multi-key targets the code is deliberately limited to a single key and is
+//! not sized across the whole region, as the gaps between keys would break
+//! the symbol.
+
+use image::{imageops, Rgb, RgbImage};
+use qrcode::{Color, EcLevel, QrCode};
+use tracing::debug;
+
+use crate::info::Kind;
+use crate::surface::Target;
+use crate::{Error, StreamDeck};
+
+const DARK: Rgb<u8> = Rgb([0, 0, 0]);
+const LIGHT: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// QR code renderer
+#[derive(Debug, Clone)]
+pub struct Qr {
+    data: String,
+    quiet_zone: u32,
+    min_module_size: u32,
+}
+
+impl Qr {
+    /// Create a QR code for the provided data
+    pub fn new(data: &str) -> Self {
+        Self {
+            data: data.to_string(),
+            quiet_zone: 4,
+            min_module_size: 2,
+        }
+    }
+
+    /// Create a QR code to join a Wi-Fi network (WPA/WPA2, or open if no password is provided)
+    pub fn wifi(ssid: &str, password: Option<&str>) -> Self {
+        // Special characters are escaped with a backslash
+        let escape = |s: &str| {
+            s.chars().fold(String::new(), |mut out, c| {
+                if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+                    out.push('\\');
+                }
+                out.push(c);
+                out
+            })
+        };
+
+        let data = match password {
+            Some(p) => format!("WIFI:T:WPA;S:{};P:{};;", escape(ssid), escape(p)),
+            None => format!("WIFI:T:nopass;S:{};;", escape(ssid)),
+        };
+        Self::new(&data)
+    }
+
+    /// Set the quiet zone (blank border) width in modules, the specification requires 4
+    pub fn quiet_zone(mut self, modules: u32) -> Self {
+        self.quiet_zone = modules;
+        self
+    }
+
+    /// Set the smallest module size in pixels considered scannable
+    pub fn min_module_size(mut self, pixels: u32) -> Self {
+        self.min_module_size = pixels.max(1);
+        self
+    }
+
+    /// Encode the code at the highest error correction level that fits `size` pixels
+    fn encode(&self, size: u32) -> Result<(QrCode, u32), Error> {
+        let mut smallest = None;
+
+        for level in [EcLevel::H, EcLevel::Q, EcLevel::M, EcLevel::L] {
+            let code = match QrCode::with_error_correction_level(&self.data, level) {
+                Ok(c) => c,
+                Err(qrcode::types::QrError::DataTooLong) => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            let modules = code.width() as u32 + 2 * self.quiet_zone;
+            let module_size = size / modules;
+            if module_size >= self.min_module_size {
+                debug!("qr code at {:?}: {} modules of {}px", level, modules, module_size);
+                return Ok((code, module_size));
+            }
+            smallest = Some((code, module_size));
+        }
+
+        // Fall back to the smallest code if it fits at all
+        match smallest {
+            Some((code, module_size)) if module_size > 0 => {
+                debug!("qr code modules of {}px may not scan reliably", module_size);
+                Ok((code, module_size))
+            }
+            _ => Err(Error::QrTooLarge),
+        }
+    }
+
+    /// Render the code centred in a `width` by `height` image
+    pub fn render(&self, width: u32, height: u32) -> Result<RgbImage, Error> {
+        let (code, module) = self.encode(width.min(height))?;
+        let n = code.width() as u32;
+        let colours = code.to_colors();
+
+        let mut image = RgbImage::from_pixel(width, height, LIGHT);
+        let (x0, y0) = ((width - n * module) / 2, (height - n * module) / 2);
+
+        for (i, c) in colours.iter().enumerate() {
+            if *c != Color::Dark {
+                continue;
+            }
+            let (mx, my) = (i as u32 % n, i as u32 / n);
+            for y in 0..module {
+                for x in 0..module {
+                    image.put_pixel(x0 + mx * module + x, y0 + my * module + y, DARK);
+                }
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Render the code for a target on a device kind
+    ///
+    /// Multi-key targets are filled white with the code on a single key (see the
+    /// [module docs](crate::qr)): the centre key, or with an even number of columns
+    /// or rows the top left of the central keys.
+    pub fn render_target(&self, kind: Kind, target: Target) -> Result<RgbImage, Error> {
+        let (w, h) = target.size(kind)?;
+        let keys = match target {
+            Target::Lcd => return self.render(w, h),
+            _ => target.keys(kind)?,
+        };
+
+        let (columns, rows) = keys.iter().fold((0, 0), |(c, r), (_, column, row)| (c.max(*column + 1), r.max(*row + 1)));
+        if columns * rows == 1 {
+            return self.render(w, h);
+        }
+
+        let (kw, kh) = kind.image_size();
+        let gap = kind.key_gap();
+        let (x, y) = ((columns - 1) / 2, (rows - 1) / 2);
+
+        let mut image = RgbImage::from_pixel(w, h, LIGHT);
+        let code = self.render(kw as u32, kh as u32)?;
+        imageops::replace(&mut image, &code, (x as usize * (kw + gap)) as i64, (y as usize * (kh + gap)) as i64);
+
+        Ok(image)
+    }
+
+    /// Render the code for a target and write it to the device
+    pub fn write(&self, deck: &mut StreamDeck, target: Target) -> Result<(), Error> {
+        let image = self.render_target(deck.kind(), target)?;
+        target.write(deck, &image)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_with_quiet_zone() {
+        let qr = Qr::new("https://example.com");
+        let image = qr.render(120, 120).unwrap();
+
+        // The quiet zone is light, and the top left finder pattern is dark
+        let (code, module) = qr.encode(120).unwrap();
+        let offset = (120 - code.width() as u32 * module) / 2;
+        assert!(offset >= 4 * module);
+        assert_eq!(*image.get_pixel(offset - 1, offset), LIGHT);
+        assert_eq!(*image.get_pixel(offset, offset), DARK);
+        assert!(module >= 2);
+
+        // Larger areas allow higher error correction
+        let (small, _) = qr.encode(72).unwrap();
+        let (large, _) = qr.encode(400).unwrap();
+        assert!(large.error_correction_level() >= small.error_correction_level());
+
+        assert!(Qr::new(&"x".repeat(2000)).render(72, 72).is_err());
+    }
+
+    #[test]
+    fn avoid_key_gaps() {
+        let (kw, gap) = (Kind::Mini.image_size().0 as u32, Kind::Mini.key_gap() as u32);
+        let target = Target::Grid { first: 0, columns: 2, rows: 2 };
+        let image = Qr::new("https://example.com").render_target(Kind::Mini, target).unwrap();
+
+        assert!(image.pixels().any(|p| *p == DARK));
+        for (x, y, p) in image.enumerate_pixels() {
+            let in_gap = (kw..kw + gap).contains(&x) || (kw..kw + gap).contains(&y);
+            assert!(!in_gap || *p == LIGHT, "dark module in gap at {},{}", x, y);
+            assert!((x < kw && y < kw) || *p == LIGHT, "dark module outside the top left key at {},{}", x, y);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Multi-key regions
+//!
+//! A [Region] is a rectangular block of keys acting as one larger display.
+//! Images and [Widget]s are rendered across the whole region (including the
+//! gaps between keys) and split onto the keys, while presses are reported
+//! with the column and row of the sub-key within the region. [Regions] routes
+//! new presses from a whole deck to the [RegionWidget] covering each key.
+
+use image::RgbImage;
+
+use crate::info::Kind;
+use crate::surface::Target;
+use crate::widgets::Widget;
+use crate::{Error, StreamDeck};
+
+/// Rectangular block of keys
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Region {
+    kind: Kind,
+    first: u8,
+    columns: u8,
+    rows: u8,
+}
+
+impl Region {
+    /// Create a region of `columns` by `rows` keys with `first` at the top left
+    pub fn new(kind: Kind, first: u8, columns: u8, rows: u8) -> Result<Self, Error> {
+        let region = Self {
+            kind,
+            first,
+            columns,
+            rows,
+        };
+        region.target().size(kind)?;
+        Ok(region)
+    }
+
+    /// Drawing target covering the region
+    pub fn target(&self) -> Target {
+        Target::Grid {
+            first: self.first,
+            columns: self.columns,
+            rows: self.rows,
+        }
+    }
+
+    /// Image size covering the region
+    pub fn size(&self) -> (u32, u32) {
+        self.target().size(self.kind).unwrap_or((0, 0))
+    }
+
+    /// List the keys in the region
+    pub fn keys(&self) -> Vec<u8> {
+        self.sub_keys().into_iter().map(|(key, _, _)| key).collect()
+    }
+
+    fn sub_keys(&self) -> Vec<(u8, u8, u8)> {
+        self.target().keys(self.kind).unwrap_or_default()
+    }
+
+    /// Find the column and row of a key within the region
+    pub fn locate(&self, key: u8) -> Option<(u8, u8)> {
+        self.sub_keys()
+            .into_iter()
+            .find(|(k, _, _)| *k == key)
+            .map(|(_, column, row)| (column, row))
+    }
+
+    pub fn contains(&self, key: u8) -> bool {
+        self.locate(key).is_some()
+    }
+
+    /// Check whether regions share any keys
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.keys().iter().any(|k| other.contains(*k))
+    }
+
+    /// Find the held sub-keys from button states (as returned by [StreamDeck::read_buttons])
+    pub fn presses(&self, states: &[u8]) -> Vec<(u8, u8)> {
+        self.sub_keys()
+            .into_iter()
+            .filter(|(key, _, _)| states.get(*key as usize).map(|s| *s != 0).unwrap_or(false))
+            .map(|(_, column, row)| (column, row))
+            .collect()
+    }
+
+    /// Write an image covering the region
+    pub fn set_image(&self, deck: &mut StreamDeck, image: &RgbImage) -> Result<(), Error> {
+        self.target().write(deck, image)
+    }
+
+    /// Render a widget across the region
+    pub fn set_widget(&self, deck: &mut StreamDeck, widget: &(impl Widget + ?Sized)) -> Result<(), Error> {
+        let (w, h) = self.size();
+        self.set_image(deck, &widget.render(w, h))
+    }
+}
+
+/// Widget spanning a region that responds to presses
+pub trait RegionWidget: Widget {
+    /// Called when a key in the region is pressed, returning whether the widget should be redrawn
+    fn pressed(&mut self, column: u8, row: u8) -> bool;
+}
+
+/// Set of non-overlapping regions with their widgets
+#[derive(Default)]
+pub struct Regions {
+    entries: Vec<(Region, Box<dyn RegionWidget>)>,
+    last_states: Vec<u8>,
+}
+
+impl Regions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a widget covering a region, returning the region index
+    ///
+    /// Regions may not share keys with existing regions.
+    pub fn add(&mut self, region: Region, widget: impl RegionWidget + 'static) -> Result<usize, Error> {
+        if self.entries.iter().any(|(r, _)| r.overlaps(&region)) {
+            return Err(Error::RegionOverlap);
+        }
+        self.entries.push((region, Box::new(widget)));
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Find the region index covering a key
+    pub fn find(&self, key: u8) -> Option<usize> {
+        self.entries.iter().position(|(r, _)| r.contains(key))
+    }
+
+    /// Route newly pressed keys to their region widgets, returning the indices of regions to redraw
+    ///
+    /// Keys held since the previous call are not routed again.
+    pub fn route(&mut self, states: &[u8]) -> Vec<usize> {
+        let pressed: Vec<u8> = states
+            .iter()
+            .enumerate()
+            .map(|(key, s)| {
+                let was_pressed = self.last_states.get(key).map(|s| *s != 0).unwrap_or(false);
+                (*s != 0 && !was_pressed) as u8
+            })
+            .collect();
+        self.last_states = states.to_vec();
+
+        let mut redraw = vec![];
+        for (i, (region, widget)) in self.entries.iter_mut().enumerate() {
+            let mut changed = false;
+            for (column, row) in region.presses(&pressed) {
+                changed |= widget.pressed(column, row);
+            }
+            if changed {
+                redraw.push(i);
+            }
+        }
+        redraw
+    }
+
+    /// Route pressed keys and redraw any widgets that changed
+    pub fn handle_buttons(&mut self, deck: &mut StreamDeck, states: &[u8]) -> Result<(), Error> {
+        for i in self.route(states) {
+            self.draw(deck, i)?;
+        }
+        Ok(())
+    }
+
+    /// Draw a single region
+    pub fn draw(&self, deck: &mut StreamDeck, index: usize) -> Result<(), Error> {
+        let (region, widget) = self.entries.get(index).ok_or(Error::InvalidKeyIndex)?;
+        region.set_widget(deck, widget.as_ref())
+    }
+
+    /// Draw all regions
+    pub fn draw_all(&self, deck: &mut StreamDeck) -> Result<(), Error> {
+        for i in 0..self.entries.len() {
+            self.draw(deck, i)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records pressed sub-keys
+    struct Pad(Rc<RefCell<Vec<(u8, u8)>>>);
+
+    impl Widget for Pad {
+        fn render(&self, width: u32, height: u32) -> RgbImage {
+            RgbImage::new(width, height)
+        }
+    }
+
+    impl RegionWidget for Pad {
+        fn pressed(&mut self, column: u8, row: u8) -> bool {
+            self.0.borrow_mut().push((column, row));
+            true
+        }
+    }
+
+    #[test]
+    fn route_presses() {
+        let region = Region::new(Kind::Mk2, 6, 2, 2).unwrap();
+        assert_eq!(region.keys(), vec![6, 7, 11, 12]);
+        assert_eq!(region.locate(12), Some((1, 1)));
+        assert_eq!(region.locate(8), None);
+
+        let presses = Rc::new(RefCell::new(vec![]));
+        let mut regions = Regions::new();
+        regions.add(region, Pad(presses.clone())).unwrap();
+        assert!(matches!(
+            regions.add(Region::new(Kind::Mk2, 7, 1, 1).unwrap(), Pad(presses.clone())),
+            Err(Error::RegionOverlap)
+        ));
+
+        let mut states = vec![0u8; 15];
+        states[11] = 1;
+        assert_eq!(regions.route(&states), vec![0]);
+        assert_eq!(regions.find(11), Some(0));
+
+        // Holding key 11 while pressing key 6 only routes the new press
+        states[6] = 1;
+        assert_eq!(regions.route(&states), vec![0]);
+        assert_eq!(*presses.borrow(), vec![(0, 1), (0, 0)]);
+
+        states[6] = 0;
+        assert!(regions.route(&states).is_empty());
+        states[11] = 0;
+        assert!(regions.route(&states).is_empty());
+        assert_eq!(presses.borrow().len(), 2);
+    }
+}
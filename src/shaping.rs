@@ -0,0 +1,340 @@
+//! Shaped text rendering for complex scripts and colour emoji
+//!
+//! [FontChain] implements [Typeface] using `rustybuzz` for shaping and
+//! `unicode-bidi` for mixed direction text, so it can be used anywhere a
+//! `rusttype::Font` is accepted for text rendering. Characters missing from the
+//! primary font are rendered using the first fallback font that contains them,
+//! and colour bitmap glyphs (`CBDT` and `sbix`, as used by most emoji fonts) are
+//! drawn in colour. `COLR` vector colour glyphs are not supported.
+
+use std::sync::Arc;
+
+use image::imageops::FilterType;
+use image::{Rgb, RgbImage};
+use rusttype::{point, Font, GlyphId, Scale};
+use rustybuzz::ttf_parser::{self, RasterImageFormat};
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use self_cell::self_cell;
+use tracing::trace;
+use unicode_bidi::ParagraphBidiInfo;
+
+use crate::images::blend;
+use crate::text::Typeface;
+use crate::Error;
+
+/// Shaping and rasterising faces parsed from the same font data
+struct Faces<'a> {
+    shaper: Face<'a>,
+    font: Font<'a>,
+}
+
+self_cell!(
+    struct ParsedFont {
+        owner: Box<[u8]>,
+
+        #[covariant]
+        dependent: Faces,
+    }
+);
+
+/// Font face loaded for shaped text rendering
+#[derive(Clone)]
+pub struct ShapedFont {
+    parsed: Arc<ParsedFont>,
+}
+
+impl ShapedFont {
+    /// Load a font from TrueType / OpenType data
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, Error> {
+        Self::from_bytes_and_index(data, 0)
+    }
+
+    /// Load a font from a TrueType / OpenType collection by face index
+    pub fn from_bytes_and_index(data: Vec<u8>, index: u32) -> Result<Self, Error> {
+        let parsed = ParsedFont::try_new(data.into_boxed_slice(), |data| {
+            Ok::<_, Error>(Faces {
+                shaper: Face::from_slice(data, index).ok_or(Error::InvalidFont)?,
+                font: Font::try_from_bytes_and_index(data, index).ok_or(Error::InvalidFont)?,
+            })
+        })?;
+
+        Ok(Self {
+            parsed: Arc::new(parsed),
+        })
+    }
+
+    /// Fetch the underlying rusttype font
+    pub fn font(&self) -> &Font<'_> {
+        &self.parsed.borrow_dependent().font
+    }
+
+    fn face(&self) -> &Face<'_> {
+        &self.parsed.borrow_dependent().shaper
+    }
+}
+
+/// Glyph positioned relative to the pen start and baseline, in pixels
+struct ShapedGlyph {
+    font: usize,
+    id: u16,
+    x: f32,
+    y: f32,
+}
+
+/// Ordered list of fonts used for shaped text rendering
+///
+/// The first font is the primary font and provides the line metrics.
+#[derive(Clone)]
+pub struct FontChain {
+    fonts: Vec<ShapedFont>,
+}
+
+impl FontChain {
+    pub fn new(primary: ShapedFont) -> Self {
+        Self {
+            fonts: vec![primary],
+        }
+    }
+
+    /// Add a fallback font for characters missing from earlier fonts
+    pub fn with_fallback(mut self, font: ShapedFont) -> Self {
+        self.fonts.push(font);
+        self
+    }
+
+    /// Add a fallback font for characters missing from earlier fonts
+    pub fn push(&mut self, font: ShapedFont) {
+        self.fonts.push(font);
+    }
+
+    /// Pixels per font unit for the font at `index`, matching rusttype scaling
+    fn units_to_px(&self, index: usize, scale: Scale) -> (f32, f32) {
+        let face = self.fonts[index].face();
+        let height = (face.ascender() - face.descender()) as f32;
+        (scale.x / height, scale.y / height)
+    }
+
+    /// Select the font used to render each character, in logical order
+    fn segment<'t>(&self, text: &'t str) -> Vec<(usize, &'t str)> {
+        let faces: Vec<_> = self.fonts.iter().map(|f| f.face()).collect();
+        let mut segments: Vec<(usize, &str)> = vec![];
+        let mut start = 0;
+        let mut current = None;
+
+        for (i, c) in text.char_indices() {
+            // Keep joiners, selectors and modifiers with the preceding character
+            let font = match current {
+                Some(f) if is_continuation(c) => f,
+                _ => faces
+                    .iter()
+                    .position(|f| f.glyph_index(c).is_some())
+                    .unwrap_or(0),
+            };
+
+            if let Some(f) = current {
+                if f != font {
+                    segments.push((f, &text[start..i]));
+                    start = i;
+                }
+            }
+            current = Some(font);
+        }
+
+        if let Some(f) = current {
+            segments.push((f, &text[start..]));
+        }
+
+        segments
+    }
+
+    /// Shape a single line of text, returning positioned glyphs and the line width
+    fn shape(&self, text: &str, scale: Scale) -> (Vec<ShapedGlyph>, f32) {
+        let mut glyphs = vec![];
+        let mut pen = 0.0;
+
+        if text.is_empty() {
+            return (glyphs, pen);
+        }
+
+        let bidi = ParagraphBidiInfo::new(text, None);
+        let (levels, runs) = bidi.visual_runs(0..text.len());
+
+        for run in runs {
+            let rtl = levels[run.start].is_rtl();
+            let mut segments = self.segment(&text[run]);
+            if rtl {
+                segments.reverse();
+            }
+
+            for (font, segment) in segments {
+                let face = self.fonts[font].face();
+                let (sx, sy) = self.units_to_px(font, scale);
+
+                let mut buffer = UnicodeBuffer::new();
+                buffer.push_str(segment);
+                buffer.set_direction(if rtl { Direction::RightToLeft } else { Direction::LeftToRight });
+
+                let output = rustybuzz::shape(face, &[], buffer);
+                for (info, pos) in output.glyph_infos().iter().zip(output.glyph_positions()) {
+                    glyphs.push(ShapedGlyph {
+                        font,
+                        id: info.glyph_id as u16,
+                        x: pen + pos.x_offset as f32 * sx,
+                        y: -pos.y_offset as f32 * sy,
+                    });
+                    pen += pos.x_advance as f32 * sx;
+                }
+            }
+        }
+
+        (glyphs, pen)
+    }
+
+    /// Draw a colour bitmap glyph, returning false if the glyph has no bitmap
+    fn draw_raster(&self, image: &mut RgbImage, g: &ShapedGlyph, x: f32, top: f32, scale: Scale) -> bool {
+        let face = self.fonts[g.font].face();
+        let (_, sy) = self.units_to_px(g.font, scale);
+        let ppem = face.units_per_em() as f32 * sy;
+
+        let raster = match face.glyph_raster_image(ttf_parser::GlyphId(g.id), ppem.round() as u16) {
+            Some(r) if r.format == RasterImageFormat::PNG => r,
+            _ => return false,
+        };
+
+        let bitmap = match image::load_from_memory(raster.data) {
+            Ok(b) => b,
+            Err(e) => {
+                trace!("error decoding bitmap glyph {}: {:?}", g.id, e);
+                return false;
+            }
+        };
+
+        // Scale the strike to the requested size and centre it in the line
+        let s = ppem / raster.pixels_per_em as f32;
+        let (w, h) = (
+            (bitmap.width() as f32 * s).round().max(1.0) as u32,
+            (bitmap.height() as f32 * s).round().max(1.0) as u32,
+        );
+        let bitmap = bitmap.resize_exact(w, h, FilterType::Triangle).to_rgba8();
+
+        let x0 = (x + g.x + raster.x as f32 * s).round() as i32;
+        let y0 = (top + g.y + (scale.y - h as f32) / 2.0).round() as i32;
+
+        for (bx, by, p) in bitmap.enumerate_pixels() {
+            blend_at(image, x0 + bx as i32, y0 + by as i32, Rgb([p.0[0], p.0[1], p.0[2]]), p.0[3] as f32 / 255.0);
+        }
+
+        true
+    }
+}
+
+impl Typeface for FontChain {
+    fn text_width(&self, text: &str, scale: Scale) -> f32 {
+        self.shape(text, scale).1
+    }
+
+    fn draw_line(&self, image: &mut RgbImage, colour: Rgb<u8>, x: i32, y: i32, scale: Scale, text: &str) {
+        let (glyphs, _) = self.shape(text, scale);
+        let ascent = self.fonts[0].font().v_metrics(scale).ascent;
+        let (x, top) = (x as f32, y as f32);
+
+        for g in &glyphs {
+            if self.draw_raster(image, g, x, top, scale) {
+                continue;
+            }
+
+            let glyph = self.fonts[g.font]
+                .font()
+                .glyph(GlyphId(g.id))
+                .scaled(scale)
+                .positioned(point(x + g.x, top + ascent + g.y));
+
+            if let Some(bb) = glyph.pixel_bounding_box() {
+                glyph.draw(|gx, gy, v| {
+                    blend_at(image, bb.min.x + gx as i32, bb.min.y + gy as i32, colour, v);
+                });
+            }
+        }
+    }
+}
+
+/// Characters that attach to the preceding character
+fn is_continuation(c: char) -> bool {
+    matches!(c as u32,
+        // Combining diacritical marks
+        0x0300..=0x036f
+        // Zero width joiner / non-joiner
+        | 0x200c..=0x200d
+        // Variation selectors
+        | 0xfe00..=0xfe0f | 0xe0100..=0xe01ef
+        // Emoji skin tone modifiers
+        | 0x1f3fb..=0x1f3ff
+        // Emoji tag sequences
+        | 0xe0020..=0xe007f)
+}
+
+/// Blend a colour into an image pixel with the provided coverage
+fn blend_at(image: &mut RgbImage, x: i32, y: i32, colour: Rgb<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+        return;
+    }
+
+    let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+    blend(image.get_pixel_mut(x as u32, y as u32), colour.0, alpha);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chain() -> FontChain {
+        let data = include_bytes!("../fonts/Tuffy.ttf").to_vec();
+        FontChain::new(ShapedFont::from_bytes(data).unwrap())
+    }
+
+    #[test]
+    fn shaped_width() {
+        let chain = chain();
+        let scale = Scale::uniform(20.0);
+
+        // Widths follow the unshaped rusttype layout for simple latin text
+        let shaped = chain.text_width("Hello", scale);
+        let plain = chain.fonts[0].font().text_width("Hello", scale);
+        assert!(shaped > 0.0 && (shaped - plain).abs() < 1.0);
+
+        assert_eq!(chain.text_width("", scale), 0.0);
+        let words = chain.text_width("ab", scale) + chain.text_width(" cd", scale);
+        assert!((chain.text_width("ab cd", scale) - words).abs() < 1.0);
+
+        assert!(ShapedFont::from_bytes(vec![0; 16]).is_err());
+    }
+
+    #[test]
+    fn shape_bidi() {
+        let chain = chain();
+        let scale = Scale::uniform(20.0);
+
+        // Mixed direction text is laid out in visual order with a single advancing pen
+        let (glyphs, width) = chain.shape("abc \u{05d0}\u{05d1}", scale);
+        assert_eq!(glyphs.len(), 6);
+        assert!(glyphs.windows(2).all(|g| g[0].x <= g[1].x));
+        assert!(glyphs.iter().all(|g| g.x < width));
+
+        let rtl = chain.text_width("\u{05d0}\u{05d1}", scale);
+        assert!((width - chain.text_width("abc ", scale) - rtl).abs() < 1.0);
+
+        // Combining marks stay with their base character
+        assert_eq!(chain.segment("e\u{0301}x").len(), 1);
+    }
+
+    #[test]
+    fn draw_shaped() {
+        let chain = chain();
+        let mut image = RgbImage::new(72, 24);
+        chain.draw_line(&mut image, Rgb([255, 255, 255]), 2, 2, Scale::uniform(20.0), "Hi");
+
+        let inked = image.pixels().filter(|p| p.0[0] > 0).count();
+        assert!(inked > 20);
+        assert!(image.enumerate_pixels().all(|(x, _, p)| x < 40 || p.0[0] == 0));
+    }
+}
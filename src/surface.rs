@@ -0,0 +1,125 @@
+//! Drawing surfaces spanning keys or the LCD
+//!
+//! A [Target] describes an area of the device drawn as one continuous image:
+//! a single key, a row or rectangular block of adjacent keys (including the
+//! physical gaps between them, see [Kind::key_gap]), or the LCD strip.
+
+use image::{imageops, DynamicImage, RgbImage};
+
+use crate::info::Kind;
+use crate::{Error, StreamDeck};
+
+/// Area of the device drawn as a single image
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Target {
+    /// A single key
+    Key(u8),
+    /// `count` adjacent keys in a row, starting at `first`
+    Keys { first: u8, count: u8 },
+    /// A block of `columns` by `rows` keys with `first` at the top left
+    Grid { first: u8, columns: u8, rows: u8 },
+    /// The LCD strip
+    Lcd,
+}
+
+impl Target {
+    /// Image size for the target on a device kind
+    pub fn size(&self, kind: Kind) -> Result<(u32, u32), Error> {
+        let (kw, kh) = kind.image_size();
+        let gap = kind.key_gap();
+
+        let (w, h) = match *self {
+            Target::Lcd => kind.lcd_size().ok_or(Error::NoLcd)?,
+            _ => {
+                let (_, columns, rows) = self.grid(kind)?;
+                let (columns, rows) = (columns as usize, rows as usize);
+                (columns * kw + (columns - 1) * gap, rows * kh + (rows - 1) * gap)
+            }
+        };
+
+        Ok((w as u32, h as u32))
+    }
+
+    /// Validate key targets, returning the first key and the number of columns and rows
+    fn grid(&self, kind: Kind) -> Result<(u8, u8, u8), Error> {
+        let (first, columns, rows) = match *self {
+            Target::Key(key) => (key, 1, 1),
+            Target::Keys { first, count } => (first, count, 1),
+            Target::Grid { first, columns, rows } => (first, columns, rows),
+            Target::Lcd => return Err(Error::InvalidKeyIndex),
+        };
+
+        let cols = kind.key_columns();
+        let total_rows = (kind.keys() as usize).div_ceil(cols as usize);
+        if columns == 0
+            || rows == 0
+            || first >= kind.keys()
+            || (first % cols) as usize + columns as usize > cols as usize
+            || (first / cols) as usize + rows as usize > total_rows
+        {
+            return Err(Error::InvalidKeyIndex);
+        }
+
+        Ok((first, columns, rows))
+    }
+
+    /// List the keys covered by the target with their column and row within the target
+    pub fn keys(&self, kind: Kind) -> Result<Vec<(u8, u8, u8)>, Error> {
+        let (first, columns, rows) = self.grid(kind)?;
+        let cols = kind.key_columns();
+
+        let mut keys = vec![];
+        for row in 0..rows {
+            for column in 0..columns {
+                keys.push((first + row * cols + column, column, row));
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Write an image covering the target to the device
+    ///
+    /// Key targets are split into per-key images, discarding the gaps between keys.
+    pub fn write(&self, deck: &mut StreamDeck, image: &RgbImage) -> Result<(), Error> {
+        let kind = deck.kind();
+        if image.dimensions() != self.size(kind)? {
+            return Err(Error::InvalidImageSize);
+        }
+
+        if let Target::Lcd = self {
+            return deck.set_lcd_image(0, 0, DynamicImage::ImageRgb8(image.clone()));
+        }
+
+        let (kw, kh) = kind.image_size();
+        let (xstep, ystep) = ((kw + kind.key_gap()) as u32, (kh + kind.key_gap()) as u32);
+
+        let keys = self.keys(kind)?.into_iter().map(|(key, column, row)| {
+            let sub = imageops::crop_imm(image, column as u32 * xstep, row as u32 * ystep, kw as u32, kh as u32);
+            (key, DynamicImage::ImageRgb8(sub.to_image()))
+        });
+        deck.set_buttons(keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn target_sizes() {
+        let gap = Kind::Mk2.key_gap() as u32;
+        assert_eq!(Target::Keys { first: 1, count: 3 }.size(Kind::Mk2).unwrap(), (3 * 72 + 2 * gap, 72));
+        assert_eq!(Target::Lcd.size(Kind::Plus).unwrap(), (800, 100));
+
+        // Rows can not wrap onto the next row
+        assert!(Target::Keys { first: 4, count: 2 }.size(Kind::Mk2).is_err());
+        assert!(Target::Key(15).size(Kind::Mk2).is_err());
+        assert!(Target::Lcd.size(Kind::Mk2).is_err());
+
+        let grid = Target::Grid { first: 1, columns: 2, rows: 2 };
+        assert_eq!(grid.size(Kind::Mini).unwrap(), (2 * 80 + 20, 2 * 80 + 20));
+        assert_eq!(grid.keys(Kind::Mini).unwrap(), vec![(1, 0, 0), (2, 1, 0), (4, 0, 1), (5, 1, 1)]);
+        assert!(Target::Grid { first: 3, columns: 2, rows: 2 }.size(Kind::Mini).is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! Text layout and rendering for keys
+//!
+//! Text is laid out into lines (splitting on `\n` and optionally wrapping to the
+//! available width), optionally shrunk to fit, then aligned and drawn with an
+//! optional shadow and outline.
+
+use std::str::FromStr;
+
+use image::{DynamicImage, Rgb, RgbImage};
+use imageproc::drawing::draw_text_mut;
+use rusttype::{point, Font};
+pub use rusttype::Scale;
+
+use crate::images::{blend, rgb, Colour};
+
+/// Font metrics and drawing used for text layout
+pub trait Typeface {
+    /// Horizontal advance of a single line of text at the provided scale
+    fn text_width(&self, text: &str, scale: Scale) -> f32;
+
+    /// Draw a single line of text with its top left corner at `x`, `y`
+    fn draw_line(&self, image: &mut RgbImage, colour: Rgb<u8>, x: i32, y: i32, scale: Scale, text: &str);
+}
+
+impl<'f> Typeface for Font<'f> {
+    fn text_width(&self, text: &str, scale: Scale) -> f32 {
+        self.layout(text, scale, point(0.0, 0.0))
+            .last()
+            .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+            .unwrap_or(0.0)
+    }
+
+    fn draw_line(&self, image: &mut RgbImage, colour: Rgb<u8>, x: i32, y: i32, scale: Scale, text: &str) {
+        draw_text_mut(image, colour, x, y, scale, self, text);
+    }
+}
+
+/// Horizontal text alignment
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HAlign {
+    Left,
+    Centre,
+    Right,
+}
+
+/// Vertical text alignment
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VAlign {
+    Top,
+    Centre,
+    Bottom,
+}
+
+/// TextPosition is how to position text via set_button_text
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextPosition {
+    /// Absolute positioning
+    Absolute { x: i32, y: i32 },
+    /// Aligned within the key, inset by `padding` pixels on each side
+    Aligned {
+        horizontal: HAlign,
+        vertical: VAlign,
+        padding: i32,
+    },
+}
+
+impl TextPosition {
+    /// Text centred horizontally and vertically
+    pub fn centred() -> Self {
+        TextPosition::Aligned {
+            horizontal: HAlign::Centre,
+            vertical: VAlign::Centre,
+            padding: 0,
+        }
+    }
+}
+
+/// Outline drawn around text glyphs
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub colour: Colour,
+    /// Outline width in pixels
+    pub width: u32,
+}
+
+/// Drop shadow drawn behind text glyphs
+#[derive(Debug, Clone)]
+pub struct Shadow {
+    pub colour: Colour,
+    /// Shadow offset in pixels
+    pub offset: (i32, i32),
+}
+
+/// Text Options provide values for text buttons
+#[derive(Debug, Clone)]
+pub struct TextOptions {
+    pub(crate) foreground: Colour,
+    pub(crate) background: Colour,
+    pub(crate) scale: Scale,
+    pub(crate) line_height: f32,
+    pub(crate) wrap: bool,
+    pub(crate) min_scale: Option<f32>,
+    pub(crate) outline: Option<Outline>,
+    pub(crate) shadow: Option<Shadow>,
+}
+
+impl TextOptions {
+    pub fn new(foreground: Colour, background: Colour, scale: Scale, line_height: f32) -> Self {
+        TextOptions {
+            foreground,
+            background,
+            scale,
+            line_height,
+            ..Default::default()
+        }
+    }
+
+    /// Set the text scale in pixels
+    pub fn with_scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Wrap words onto new lines to fit the available width
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Shrink the text (down to `min_scale` pixels high) until it fits the key
+    pub fn with_shrink_to_fit(mut self, min_scale: f32) -> Self {
+        self.min_scale = Some(min_scale);
+        self
+    }
+
+    /// Draw an outline of `width` pixels around the text
+    pub fn with_outline(mut self, colour: Colour, width: u32) -> Self {
+        self.outline = Some(Outline { colour, width });
+        self
+    }
+
+    /// Draw a shadow behind the text, offset by `dx`, `dy` pixels
+    pub fn with_shadow(mut self, colour: Colour, dx: i32, dy: i32) -> Self {
+        self.shadow = Some(Shadow {
+            colour,
+            offset: (dx, dy),
+        });
+        self
+    }
+}
+
+impl Default for TextOptions {
+    /// default is white text on a black background, with 15 pixel high text
+    /// and 1.1x the line height.
+    fn default() -> Self {
+        TextOptions {
+            foreground: Colour::from_str("FFFFFF").unwrap(),
+            background: Colour::from_str("000000").unwrap(),
+            scale: Scale { x: 15.0, y: 15.0 },
+            line_height: 1.1,
+            wrap: false,
+            min_scale: None,
+            outline: None,
+            shadow: None,
+        }
+    }
+}
+
+/// Text laid out into lines at a given scale
+struct Layout {
+    lines: Vec<(String, f32)>,
+    scale: Scale,
+    height: f32,
+}
+
+/// Render text onto a new `width` by `height` image filled with the background colour
+pub fn render_text<F: Typeface + ?Sized>(
+    width: u32,
+    height: u32,
+    font: &F,
+    pos: &TextPosition,
+    text: &str,
+    opts: &TextOptions,
+) -> RgbImage {
+    let mut image = RgbImage::from_pixel(width, height, rgb(&opts.background));
+    draw_text(&mut image, font, pos, text, opts);
+    image
+}
+
+/// Render text over an existing image, resized to `width` by `height`
+///
+/// Transparent areas of the image are filled with the background colour.
+pub fn render_text_over<F: Typeface + ?Sized>(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    font: &F,
+    pos: &TextPosition,
+    text: &str,
+    opts: &TextOptions,
+) -> RgbImage {
+    let mut canvas = RgbImage::from_pixel(width, height, rgb(&opts.background));
+
+    let image = image.resize_to_fill(width, height, image::imageops::FilterType::Gaussian);
+    for (p, src) in canvas.pixels_mut().zip(image.to_rgba8().pixels()) {
+        let [r, g, b, a] = src.0;
+        blend(p, [r, g, b], a);
+    }
+
+    draw_text(&mut canvas, font, pos, text, opts);
+    canvas
+}
+
+/// Draw text onto an existing image
+pub fn draw_text<F: Typeface + ?Sized>(
+    image: &mut RgbImage,
+    font: &F,
+    pos: &TextPosition,
+    text: &str,
+    opts: &TextOptions,
+) {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+
+    // Compute the area available to the text
+    let (avail_w, avail_h) = match pos {
+        TextPosition::Absolute { x, y } => (width - *x as f32, height - *y as f32),
+        TextPosition::Aligned { padding, .. } => (
+            width - 2.0 * *padding as f32,
+            height - 2.0 * *padding as f32,
+        ),
+    };
+
+    let layout = layout_text(font, text, opts, avail_w, avail_h);
+    let advance = layout.scale.y * opts.line_height;
+
+    for (i, (line, line_width)) in layout.lines.iter().enumerate() {
+        let (x, y) = match pos {
+            TextPosition::Absolute { x, y } => (*x, *y + (i as f32 * advance).round() as i32),
+            TextPosition::Aligned {
+                horizontal,
+                vertical,
+                padding,
+            } => {
+                let padding = *padding as f32;
+                let x = match horizontal {
+                    HAlign::Left => padding,
+                    HAlign::Centre => (width - line_width) / 2.0,
+                    HAlign::Right => width - padding - line_width,
+                };
+                let top = match vertical {
+                    VAlign::Top => padding,
+                    VAlign::Centre => (height - layout.height) / 2.0,
+                    VAlign::Bottom => height - padding - layout.height,
+                };
+                (x.round() as i32, (top + i as f32 * advance).round() as i32)
+            }
+        };
+
+        if let Some(s) = &opts.shadow {
+            let (dx, dy) = s.offset;
+            with_alpha(image, &s.colour, |layer, c| {
+                font.draw_line(layer, c, x + dx, y + dy, layout.scale, line)
+            });
+        }
+
+        if let Some(o) = &opts.outline {
+            let w = o.width as i32;
+            with_alpha(image, &o.colour, |layer, c| {
+                for dy in -w..=w {
+                    for dx in -w..=w {
+                        if (dx, dy) != (0, 0) && dx * dx + dy * dy <= w * w {
+                            font.draw_line(layer, c, x + dx, y + dy, layout.scale, line);
+                        }
+                    }
+                }
+            });
+        }
+
+        with_alpha(image, &opts.foreground, |layer, c| {
+            font.draw_line(layer, c, x, y, layout.scale, line)
+        });
+    }
+}
+
+/// Lay out text into lines, shrinking the scale if required to fit
+fn layout_text<F: Typeface + ?Sized>(
+    font: &F,
+    text: &str,
+    opts: &TextOptions,
+    avail_w: f32,
+    avail_h: f32,
+) -> Layout {
+    let mut scale = opts.scale;
+
+    loop {
+        let lines = split_lines(font, text, scale, opts.wrap, avail_w);
+        let height = match lines.len() {
+            0 => 0.0,
+            n => (n - 1) as f32 * scale.y * opts.line_height + scale.y,
+        };
+
+        let fits = height <= avail_h && lines.iter().all(|(_, w)| *w <= avail_w);
+
+        match opts.min_scale {
+            Some(min) if !fits && scale.y > min => {
+                // Shrink by 10% (and at least a pixel) each iteration
+                let next = (scale.y * 0.9).min(scale.y - 1.0).max(min);
+                let ratio = next / scale.y;
+                scale = Scale {
+                    x: scale.x * ratio,
+                    y: next,
+                };
+            }
+            _ => return Layout { lines, scale, height },
+        }
+    }
+}
+
+/// Split text on newlines, and wrap to `max_width` if enabled
+fn split_lines<F: Typeface + ?Sized>(
+    font: &F,
+    text: &str,
+    scale: Scale,
+    wrap: bool,
+    max_width: f32,
+) -> Vec<(String, f32)> {
+    let mut lines = vec![];
+
+    for paragraph in text.split('\n') {
+        if !wrap {
+            lines.push((paragraph.to_string(), font.text_width(paragraph, scale)));
+            continue;
+        }
+
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+
+            if font.text_width(&candidate, scale) <= max_width {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                let w = font.text_width(&line, scale);
+                lines.push((std::mem::take(&mut line), w));
+            }
+
+            // Break words that are wider than a line on their own
+            for c in word.chars() {
+                line.push(c);
+                if line.chars().count() > 1 && font.text_width(&line, scale) > max_width {
+                    line.pop();
+                    let w = font.text_width(&line, scale);
+                    lines.push((std::mem::replace(&mut line, c.to_string()), w));
+                }
+            }
+        }
+
+        let w = font.text_width(&line, scale);
+        lines.push((line, w));
+    }
+
+    lines
+}
+
+/// Draw onto the image with the colour's alpha applied
+///
+/// Opaque colours are drawn directly, otherwise drawing happens on a copy of
+/// the image which is then blended back so overlapping strokes don't accumulate.
+fn with_alpha<D: FnOnce(&mut RgbImage, Rgb<u8>)>(image: &mut RgbImage, colour: &Colour, draw: D) {
+    let c = Rgb([colour.r, colour.g, colour.b]);
+    match colour.a {
+        0 => (),
+        255 => draw(image, c),
+        a => {
+            let mut layer = image.clone();
+            draw(&mut layer, c);
+
+            for (p, l) in image.pixels_mut().zip(layer.pixels()) {
+                blend(p, l.0, a);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Fixed width typeface for layout tests
+    struct Mono;
+
+    impl Typeface for Mono {
+        fn text_width(&self, text: &str, scale: Scale) -> f32 {
+            text.chars().count() as f32 * scale.x / 2.0
+        }
+
+        fn draw_line(&self, _image: &mut RgbImage, _colour: Rgb<u8>, _x: i32, _y: i32, _scale: Scale, _text: &str) {}
+    }
+
+    #[test]
+    fn wrap_lines() {
+        let lines = split_lines(&Mono, "one two three\nfour", Scale::uniform(10.0), true, 40.0);
+        let lines: Vec<_> = lines.iter().map(|(l, _)| l.as_str()).collect();
+        assert_eq!(lines, vec!["one two", "three", "four"]);
+
+        let lines = split_lines(&Mono, "abcdefghij", Scale::uniform(10.0), true, 20.0);
+        let lines: Vec<_> = lines.iter().map(|(l, _)| l.as_str()).collect();
+        assert_eq!(lines, vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let opts = TextOptions::default().with_shrink_to_fit(6.0);
+        let layout = layout_text(&Mono, "a long label", &opts, 50.0, 72.0);
+        assert!(layout.lines[0].1 <= 50.0);
+        assert!(layout.scale.y < 15.0 && layout.scale.y >= 6.0);
+    }
+}
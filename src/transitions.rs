@@ -0,0 +1,177 @@
+//! Transition effects between key images
+//!
+//! Transitions generate intermediate frames between the current key contents
+//! (from the host [Framebuffer](crate::Framebuffer), or black where unknown)
+//! and a new image, and are played over a configurable duration using
+//! [StreamDeck::transition_button](crate::StreamDeck::transition_button) or
+//! [StreamDeck::transition_buttons](crate::StreamDeck::transition_buttons).
+
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, Rgb, RgbImage};
+use tracing::trace;
+
+use crate::{Error, StreamDeck};
+
+/// Minimum time between frames (about 60 fps) for transitions and animations
+pub(crate) const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Direction a transition moves in
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Direction {
+    /// Moving towards the left
+    Left,
+    /// Moving towards the right
+    Right,
+    Up,
+    Down,
+}
+
+/// Effect used to switch between key images
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Transition {
+    /// Blend from the old image to the new image
+    Crossfade,
+    /// Slide the new image in, pushing the old image out
+    Slide(Direction),
+    /// Reveal the new image behind a moving edge
+    Wipe(Direction),
+}
+
+impl Transition {
+    /// Render the frame at progress `t` (0.0 to 1.0) between images of the same size
+    pub fn frame(&self, from: &RgbImage, to: &RgbImage, t: f32) -> RgbImage {
+        let (w, h) = to.dimensions();
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Transition::Crossfade => RgbImage::from_fn(w, h, |x, y| {
+                let (a, b) = (from.get_pixel(x, y), to.get_pixel(x, y));
+                let mix = |i: usize| (a.0[i] as f32 + (b.0[i] as f32 - a.0[i] as f32) * t).round() as u8;
+                Rgb([mix(0), mix(1), mix(2)])
+            }),
+            Transition::Slide(dir) => {
+                // Offset of the old image, the new image follows directly behind it
+                let (dx, dy) = offset(*dir, w, h, t);
+                RgbImage::from_fn(w, h, |x, y| {
+                    let (sx, sy) = (x as i64 - dx, y as i64 - dy);
+                    if sx >= 0 && sy >= 0 && sx < w as i64 && sy < h as i64 {
+                        *from.get_pixel(sx as u32, sy as u32)
+                    } else {
+                        let (nx, ny) = (sx.rem_euclid(w as i64), sy.rem_euclid(h as i64));
+                        *to.get_pixel(nx as u32, ny as u32)
+                    }
+                })
+            }
+            Transition::Wipe(dir) => {
+                let (ew, eh) = ((w as f32 * t).round() as u32, (h as f32 * t).round() as u32);
+                RgbImage::from_fn(w, h, |x, y| {
+                    let revealed = match dir {
+                        Direction::Left => x >= w - ew,
+                        Direction::Right => x < ew,
+                        Direction::Up => y >= h - eh,
+                        Direction::Down => y < eh,
+                    };
+                    if revealed {
+                        *to.get_pixel(x, y)
+                    } else {
+                        *from.get_pixel(x, y)
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Offset of the outgoing image for a slide at progress `t`
+fn offset(dir: Direction, w: u32, h: u32, t: f32) -> (i64, i64) {
+    let (dw, dh) = ((w as f32 * t).round() as i64, (h as f32 * t).round() as i64);
+    match dir {
+        Direction::Left => (-dw, 0),
+        Direction::Right => (dw, 0),
+        Direction::Up => (0, -dh),
+        Direction::Down => (0, dh),
+    }
+}
+
+/// Play a transition on a set of keys simultaneously
+pub(crate) fn play(
+    deck: &mut StreamDeck,
+    transition: Transition,
+    images: Vec<(u8, DynamicImage)>,
+    duration: Duration,
+) -> Result<(), Error> {
+    let (w, h) = deck.image_size();
+
+    // Collect the old and new images for each key
+    let mut keys = vec![];
+    for (key, image) in images {
+        if key >= deck.kind().keys() {
+            return Err(Error::InvalidKeyIndex);
+        }
+        let to = image.into_rgb8();
+        if to.dimensions() != (w as u32, h as u32) {
+            return Err(Error::InvalidImageSize);
+        }
+        let from = match deck.framebuffer().key(key) {
+            Some(k) => k.clone(),
+            None => RgbImage::new(w as u32, h as u32),
+        };
+        keys.push((key, from, to));
+    }
+
+    let start = Instant::now();
+    loop {
+        let frame_start = Instant::now();
+        let t = match duration.as_secs_f32() {
+            d if d > 0.0 => start.elapsed().as_secs_f32() / d,
+            _ => 1.0,
+        };
+        trace!("transition frame at {:.2}", t);
+
+        let frames = keys
+            .iter()
+            .map(|(key, from, to)| (*key, DynamicImage::ImageRgb8(transition.frame(from, to, t))));
+        deck.set_buttons(frames)?;
+
+        if t >= 1.0 {
+            break;
+        }
+
+        if let Some(wait) = FRAME_INTERVAL.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transition_frames() {
+        let from = RgbImage::from_pixel(10, 10, Rgb([0, 0, 0]));
+        let to = RgbImage::from_pixel(10, 10, Rgb([200, 100, 50]));
+
+        let f = Transition::Crossfade.frame(&from, &to, 0.5);
+        assert_eq!(f.get_pixel(3, 3).0, [100, 50, 25]);
+
+        // New image enters from the right as the old image moves left
+        let f = Transition::Slide(Direction::Left).frame(&from, &to, 0.3);
+        assert_eq!(f.get_pixel(6, 0).0, [0, 0, 0]);
+        assert_eq!(f.get_pixel(7, 0).0, [200, 100, 50]);
+
+        let f = Transition::Wipe(Direction::Down).frame(&from, &to, 0.3);
+        assert_eq!(f.get_pixel(0, 2).0, [200, 100, 50]);
+        assert_eq!(f.get_pixel(0, 3).0, [0, 0, 0]);
+
+        for t in [Transition::Crossfade, Transition::Slide(Direction::Up), Transition::Wipe(Direction::Left)] {
+            assert_eq!(t.frame(&from, &to, 0.0), from);
+            assert_eq!(t.frame(&from, &to, 1.0), to);
+        }
+    }
+}
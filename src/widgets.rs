@@ -0,0 +1,332 @@
+//! Value display widgets for keys and the LCD
+//!
+//! Widgets hold their current value, which can be updated cheaply, and are
+//! rendered to a buffer of any size so they can be used on keys (see
+//! [StreamDeck::set_button_widget](crate::StreamDeck::set_button_widget)) or
+//! LCD segments (see [StreamDeck::set_lcd_widget](crate::StreamDeck::set_lcd_widget)).
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use image::RgbImage;
+use imageproc::drawing::draw_antialiased_line_segment_mut;
+use imageproc::pixelops::interpolate;
+
+use crate::images::{rgb, Colour};
+
+/// Widget that can be rendered to an image
+pub trait Widget {
+    /// Render the widget to an image of the provided size
+    fn render(&self, width: u32, height: u32) -> RgbImage;
+}
+
+/// Progress bar direction
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Orientation {
+    /// Fills from left to right
+    Horizontal,
+    /// Fills from bottom to top
+    Vertical,
+}
+
+/// Horizontal or vertical progress bar
+#[derive(Debug, Clone)]
+pub struct ProgressBar {
+    value: f32,
+    orientation: Orientation,
+    foreground: Colour,
+    background: Colour,
+    padding: u32,
+}
+
+impl ProgressBar {
+    pub fn new(orientation: Orientation) -> Self {
+        Self {
+            value: 0.0,
+            orientation,
+            foreground: Colour::rgb(40, 200, 80),
+            background: Colour::rgb(40, 40, 40),
+            padding: 4,
+        }
+    }
+
+    /// Set the bar colours
+    pub fn colours(mut self, foreground: Colour, background: Colour) -> Self {
+        self.foreground = foreground;
+        self.background = background;
+        self
+    }
+
+    /// Set the padding around the bar in pixels
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Set the progress value (0.0 to 1.0)
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value.clamp(0.0, 1.0);
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+}
+
+impl Widget for ProgressBar {
+    fn render(&self, width: u32, height: u32) -> RgbImage {
+        let mut image = RgbImage::new(width, height);
+        let pad = self.padding;
+        let (bw, bh) = (width.saturating_sub(2 * pad), height.saturating_sub(2 * pad));
+
+        let filled = match self.orientation {
+            Orientation::Horizontal => (bw as f32 * self.value).round() as u32,
+            Orientation::Vertical => (bh as f32 * self.value).round() as u32,
+        };
+
+        for y in 0..bh {
+            for x in 0..bw {
+                let on = match self.orientation {
+                    Orientation::Horizontal => x < filled,
+                    Orientation::Vertical => bh - y <= filled,
+                };
+                let c = if on { &self.foreground } else { &self.background };
+                image.put_pixel(pad + x, pad + y, rgb(c));
+            }
+        }
+
+        image
+    }
+}
+
+/// Circular arc gauge
+#[derive(Debug, Clone)]
+pub struct Gauge {
+    value: f32,
+    sweep: f32,
+    thickness: f32,
+    foreground: Colour,
+    track: Colour,
+}
+
+impl Gauge {
+    /// Create a gauge sweeping 270 degrees, open at the bottom
+    pub fn new() -> Self {
+        Self {
+            value: 0.0,
+            sweep: 270.0,
+            thickness: 0.2,
+            foreground: Colour::rgb(40, 160, 240),
+            track: Colour::rgb(40, 40, 40),
+        }
+    }
+
+    /// Set the gauge colours
+    pub fn colours(mut self, foreground: Colour, track: Colour) -> Self {
+        self.foreground = foreground;
+        self.track = track;
+        self
+    }
+
+    /// Set the arc sweep in degrees (up to 360)
+    pub fn sweep(mut self, degrees: f32) -> Self {
+        self.sweep = degrees.clamp(1.0, 360.0);
+        self
+    }
+
+    /// Set the arc thickness as a proportion of the gauge radius
+    pub fn thickness(mut self, thickness: f32) -> Self {
+        self.thickness = thickness.clamp(0.01, 1.0);
+        self
+    }
+
+    /// Set the gauge value (0.0 to 1.0)
+    pub fn set_value(&mut self, value: f32) {
+        self.value = value.clamp(0.0, 1.0);
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for Gauge {
+    fn render(&self, width: u32, height: u32) -> RgbImage {
+        let mut image = RgbImage::new(width, height);
+
+        let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+        let outer = cx.min(cy) - 2.0;
+        let inner = outer * (1.0 - self.thickness);
+
+        // Angles are measured clockwise from the bottom of the gauge
+        let start = (360.0 - self.sweep) / 2.0;
+        let end = start + self.sweep * self.value;
+
+        for (x, y, p) in image.enumerate_pixels_mut() {
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            let d = (dx * dx + dy * dy).sqrt();
+
+            // Antialias the inner and outer edges
+            let coverage = (outer - d + 0.5).clamp(0.0, 1.0) * (d - inner + 0.5).clamp(0.0, 1.0);
+            if coverage <= 0.0 {
+                continue;
+            }
+
+            let angle = (dy.atan2(dx) * 180.0 / PI + 270.0) % 360.0;
+            if angle < start || angle > start + self.sweep {
+                continue;
+            }
+
+            let c = if angle <= end { &self.foreground } else { &self.track };
+            *p = interpolate(rgb(c), *p, coverage);
+        }
+
+        image
+    }
+}
+
+/// Line chart of recent values
+#[derive(Debug, Clone)]
+pub struct Sparkline {
+    history: VecDeque<f32>,
+    capacity: usize,
+    range: Option<(f32, f32)>,
+    colour: Colour,
+    fill: Option<Colour>,
+}
+
+impl Sparkline {
+    /// Create a sparkline displaying up to `capacity` values
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(2),
+            range: None,
+            colour: Colour::rgb(240, 200, 40),
+            fill: None,
+        }
+    }
+
+    /// Set a fixed value range, otherwise the range of the history is used
+    pub fn range(mut self, min: f32, max: f32) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Set the line colour
+    pub fn colour(mut self, colour: Colour) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    /// Fill the area below the line
+    pub fn fill(mut self, colour: Colour) -> Self {
+        self.fill = Some(colour);
+        self
+    }
+
+    /// Append a value, discarding the oldest value when full
+    pub fn push(&mut self, value: f32) {
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(value);
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &f32> {
+        self.history.iter()
+    }
+}
+
+impl Widget for Sparkline {
+    fn render(&self, width: u32, height: u32) -> RgbImage {
+        let mut image = RgbImage::new(width, height);
+        if self.history.len() < 2 || width < 2 || height < 2 {
+            return image;
+        }
+
+        let (min, max) = self.range.unwrap_or_else(|| {
+            self.history
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)))
+        });
+        let span = if max > min { max - min } else { 1.0 };
+
+        // Values are right aligned so new values appear at the right hand edge
+        let step = (width - 1) as f32 / (self.capacity - 1) as f32;
+        let offset = self.capacity - self.history.len();
+        let points: Vec<(i32, i32)> = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let x = ((offset + i) as f32 * step).round() as i32;
+                let t = ((v - min) / span).clamp(0.0, 1.0);
+                let y = ((1.0 - t) * (height - 1) as f32).round() as i32;
+                (x, y)
+            })
+            .collect();
+
+        if let Some(fill) = &self.fill {
+            for w in points.windows(2) {
+                let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+                for x in x0..=x1 {
+                    let t = if x1 > x0 { (x - x0) as f32 / (x1 - x0) as f32 } else { 0.0 };
+                    let y = (y0 as f32 + (y1 - y0) as f32 * t).round() as u32;
+                    for yy in y..height {
+                        image.put_pixel(x as u32, yy, rgb(fill));
+                    }
+                }
+            }
+        }
+
+        for w in points.windows(2) {
+            draw_antialiased_line_segment_mut(&mut image, w[0], w[1], rgb(&self.colour), interpolate);
+        }
+
+        image
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn progress_bar() {
+        let mut bar = ProgressBar::new(Orientation::Horizontal).padding(0);
+        bar.set_value(0.5);
+
+        let image = bar.render(100, 10);
+        assert_eq!(image.get_pixel(49, 5).0, [40, 200, 80]);
+        assert_eq!(image.get_pixel(50, 5).0, [40, 40, 40]);
+    }
+
+    #[test]
+    fn gauge() {
+        let mut gauge = Gauge::new();
+        gauge.set_value(0.5);
+
+        // Half way around a 270 degree sweep is the top of the gauge
+        let image = gauge.render(72, 72);
+        assert_eq!(image.get_pixel(30, 5).0, [40, 160, 240]);
+        assert_eq!(image.get_pixel(42, 5).0, [40, 40, 40]);
+        // The bottom is open
+        assert_eq!(image.get_pixel(36, 68).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn sparkline_history() {
+        let mut spark = Sparkline::new(3);
+        for v in 0..5 {
+            spark.push(v as f32);
+        }
+        assert_eq!(spark.values().cloned().collect::<Vec<_>>(), vec![2.0, 3.0, 4.0]);
+    }
+}